@@ -0,0 +1,181 @@
+use crate::co_occurrence::Relation;
+use crate::graph::{build_matrix, triplets};
+use matrixlab::MatrixElement;
+use std::collections::BTreeMap;
+
+/// The statistically significant edges of a `Relation`
+pub struct Backbone {
+    relation: Relation,
+    // Key: (i, j) with i < j Value: significance of the edge
+    significance: BTreeMap<(usize, usize), f64>,
+}
+
+impl Backbone {
+    /// The relation with only the backbone edges. Ingredient IDs, counts and the
+    /// diagonal are the same as the original relation.
+    pub fn get_relation(&self) -> &Relation {
+        &self.relation
+    }
+
+    /// Significance of every retained edge keyed on the ingredient IDs (i, j) with
+    /// i < j. What the value means depends on the method used, see
+    /// `disparity_filter` and `noise_corrected`.
+    pub fn get_significance(&self) -> &BTreeMap<(usize, usize), f64> {
+        &self.significance
+    }
+
+    pub fn into_parts(self) -> (Relation, BTreeMap<(usize, usize), f64>) {
+        (self.relation, self.significance)
+    }
+}
+
+/// Serrano et. al.'s disparity filter. An edge is kept when its weight is
+/// significant, at level alpha, for either of its ingredients under the null model
+/// of an ingredient's strength being spread uniformly at random over its edges. The
+/// significance of a kept edge is its p-value, min(alpha_ij, alpha_ji) where
+/// alpha_ij = (1 - w_ij / s_i)^(k_i - 1).
+pub fn disparity_filter(relation: &Relation, alpha: f64) -> Backbone {
+    let matrix = relation.get_matrix();
+    let n = matrix.num_rows();
+    let mut strengths = vec![0.0; n];
+    let mut degrees = vec![0; n];
+
+    for (i, j, weight) in triplets(matrix) {
+        if i != j {
+            strengths[i] += weight as f64;
+            degrees[i] += 1;
+        }
+    }
+
+    let p_value = |i: usize, weight: f64| -> f64 {
+        if degrees[i] < 2 {
+            1.0
+        } else {
+            (1.0 - weight / strengths[i]).powi(degrees[i] as i32 - 1)
+        }
+    };
+
+    backbone(relation, |i, j, weight| {
+        let significance = p_value(i, weight).min(p_value(j, weight));
+        if significance < alpha {
+            Some(significance)
+        } else {
+            None
+        }
+    })
+}
+
+/// Coscia and Neffke's noise corrected backbone. Each edge weight is compared with
+/// the weight expected from the strengths of its ingredients, and kept when the
+/// transformed lift is more than delta standard deviations above zero. The
+/// significance of a kept edge is that number of standard deviations.
+pub fn noise_corrected(relation: &Relation, delta: f64) -> Backbone {
+    let matrix = relation.get_matrix();
+    let mut strengths = vec![0.0; matrix.num_rows()];
+
+    for (i, j, weight) in triplets(matrix) {
+        if i != j {
+            strengths[i] += weight as f64;
+        }
+    }
+    let total: f64 = strengths.iter().sum();
+
+    backbone(relation, |i, j, weight| {
+        let (row, column) = (strengths[i], strengths[j]);
+        let kappa = total / (row * column);
+        let score = (kappa * weight - 1.0) / (kappa * weight + 1.0);
+
+        // beta prior on the probability of an edge from the strengths alone
+        let prior_mean = row * column / (total * total);
+        let prior_variance = row * column * (total - row) * (total - column)
+            / (total * total * total * total * (total - 1.0));
+        let alpha_prior =
+            prior_mean * prior_mean / prior_variance * (1.0 - prior_mean) - prior_mean;
+        let beta_prior =
+            prior_mean / prior_variance * (1.0 - prior_mean * prior_mean) - (1.0 - prior_mean);
+
+        let alpha_post = alpha_prior + weight;
+        let beta_post = total - weight + beta_prior;
+        let expected = alpha_post / (alpha_post + beta_post);
+        let weight_variance = expected * (1.0 - expected) * total;
+
+        // delta method for the variance of the transformed lift
+        let derivative = 1.0 / (row * column) - total * (row + column) / (row * column).powi(2);
+        let score_variance = weight_variance
+            * (2.0 * (kappa + weight * derivative) / (kappa * weight + 1.0).powi(2)).powi(2);
+        let deviation = score_variance.sqrt();
+
+        if deviation > 0.0 && score - delta * deviation > 0.0 {
+            Some(score / deviation)
+        } else {
+            None
+        }
+    })
+}
+
+// Keeps the diagonal and every edge the test returns a significance for
+fn backbone<F>(relation: &Relation, mut test: F) -> Backbone
+where
+    F: FnMut(usize, usize, f64) -> Option<f64>,
+{
+    let matrix = relation.get_matrix();
+    let mut significance = BTreeMap::new();
+    let mut matrix_elements = vec![];
+
+    for (i, j, weight) in triplets(matrix) {
+        if i == j {
+            matrix_elements.push(MatrixElement::new(i, j, weight));
+        } else if i < j {
+            if let Some(value) = test(i, j, weight as f64) {
+                significance.insert((i, j), value);
+                matrix_elements.push(MatrixElement::new(i, j, weight));
+                matrix_elements.push(MatrixElement::new(j, i, weight));
+            }
+        }
+    }
+
+    let n = matrix.num_rows();
+    let matrix = build_matrix(n, n, matrix_elements);
+
+    Backbone {
+        relation: relation.with_matrix(matrix),
+        significance,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::co_occurrence::make_relation;
+
+    // a and b share four recipes, every other pair of a, b, c and d shares one
+    async fn relation() -> Relation {
+        let mut recipes = vec![(0, vec!["a".into(), "b".into()]); 4];
+        recipes.push((4, vec!["a".into(), "c".into()]));
+        recipes.push((5, vec!["b".into(), "d".into()]));
+        recipes.push((6, vec!["c".into(), "d".into()]));
+        make_relation(&recipes).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn disparity_filter_keeps_dominant_edges() {
+        let backbone = disparity_filter(&relation().await, 0.3);
+        let significance = backbone.get_significance();
+
+        assert_eq!(significance.keys().collect::<Vec<_>>(), vec![&(0, 1)]);
+        assert!((significance[&(0, 1)] - 0.2).abs() < 1e-9);
+        assert_eq!(backbone.get_relation().get_counts(), vec![5, 5, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn noise_corrected_keeps_edges_above_expectation() {
+        let backbone = noise_corrected(&relation().await, 2.0);
+        let significance = backbone.get_significance();
+
+        assert_eq!(
+            significance.keys().collect::<Vec<_>>(),
+            vec![&(0, 1), &(2, 3)]
+        );
+        assert!((significance[&(0, 1)] - 8.41995).abs() < 1e-4);
+    }
+}
@@ -1,28 +1,32 @@
-use anyhow::Result;
-use recipe_analysis::{co_occurrence, expanded, hierarchy, louvain, recipe};
+use anyhow::{Context, Result};
+use recipe_analysis::embedding::{Embedder, RandomWalk};
+use recipe_analysis::{co_occurrence, connect_db, expanded, hierarchy, louvain, recipe, Databases};
 use std::fs;
 use std::io::prelude::*;
-use std::io::{self, Write};
-use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
+use std::io;
+use std::path::PathBuf;
 
 //use recipe_analysis::graph_explorer;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // directory the output files are written to, it must already exist
+    let out_dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| "temp".into()));
+
     let target_ingredients = vec!["steak", "tofu"];
-    let target_ingredients = target_ingredients
+    let target_ingredients: Vec<String> = target_ingredients
         .into_iter()
         .map(|name| String::from(name))
         .collect();
     println!("querying recipes");
-    let recipes = recipe::query_filtered_recipes(None, None, Some(target_ingredients)).await?;
+    let client = connect_db(Databases::Recipes).await?;
+    let filter = recipe::RecipeFilter::new().any_ingredients(target_ingredients);
+    let recipes = recipe::query_filtered_recipes(&client, &filter).await?;
 
     /*
     println!("querying recipes");
-    let recipes =
-        recipe::query_filtered_recipes(Some("Salads And Dressings".into()), None, None).await?;
+    let filter = recipe::RecipeFilter::new().tag("Salads And Dressings");
+    let recipes = recipe::query_filtered_recipes(&client, &filter).await?;
     */
 
     let num_recipes = recipes.len();
@@ -32,25 +36,14 @@ async fn main() -> Result<()> {
     );
 
     let relation = co_occurrence::make_relation(&recipes).await.unwrap();
-    relation.write_files();
-
-    let (_, _, matrix, _) = relation.into_parts();
-    let matrix_string = recipe_analysis::export(&matrix);
-
-    let mut child = Command::new("../graph-embed/build/examples/embed")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    let child_stdin = child.stdin.as_mut().unwrap();
-    child_stdin.write_all(matrix_string.as_bytes()).await?;
-    drop(child_stdin);
+    relation.write_files(&out_dir)?;
 
-    let output = child.wait_with_output().await?;
-    let output = String::from_utf8(output.stdout).unwrap();
+    println!("embedding ingredients");
+    let embedding = RandomWalk::default().embed(&relation);
 
-    let mut file = fs::File::create("temp/embedding.txt").unwrap();
-    file.write_all(output.as_bytes()).unwrap();
+    let path = out_dir.join("embedding.txt");
+    let file = fs::File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    embedding.write(io::BufWriter::new(file))?;
     /*
     let partition = graph_explorer::load_partfile();
     let coords = graph_explorer::load_coords();
@@ -80,10 +73,10 @@ async fn main() -> Result<()> {
     )
     .await;
 
-    expanded_relation.build_coolist().await;
     let nnv = expanded_relation.number_of_vertices();
     println!("louvains - number of vetices: {}", nnv);
-    let interpolation_matrices = louvain::louvain(expanded_relation.number_of_vertices()).await;
+    let adjacency = expanded_relation.build_adjacency_matrix().await;
+    let interpolation_matrices = louvain::louvain(&adjacency);
 
     let hierarchy = hierarchy::Hierarchy::new(
         interpolation_matrices,
@@ -1,13 +1,73 @@
-use anyhow::Result;
+use crate::graph::{build_matrix, triplets};
+use anyhow::{Context, Result};
 use matrixlab::matrix::sparse::SparseMatrix;
 use matrixlab::MatrixElement;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
+/// Measures of how strongly two ingredients are associated, normalized so
+/// ingredients that appear in nearly every recipe don't dominate. With N recipes,
+/// c_i recipes containing ingredient i and c_ij containing both i and j:
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Association {
+    /// Pointwise mutual information, ln(c_ij * N / (c_i * c_j))
+    Pmi,
+    /// PMI divided by -ln(c_ij / N), bounded to [-1, 1]
+    Npmi,
+    /// c_ij / (c_i + c_j - c_ij)
+    Jaccard,
+    /// c_ij / sqrt(c_i * c_j)
+    Cosine,
+    /// c_ij * N / (c_i * c_j)
+    Lift,
+}
+
+impl Association {
+    pub(crate) fn score(self, pair: f64, first: f64, second: f64, recipes: f64) -> f64 {
+        match self {
+            Association::Pmi => (pair * recipes / (first * second)).ln(),
+            Association::Npmi => {
+                let probability = pair / recipes;
+                if probability >= 1.0 {
+                    1.0
+                } else {
+                    (pair * recipes / (first * second)).ln() / -probability.ln()
+                }
+            }
+            Association::Jaccard => pair / (first + second - pair),
+            Association::Cosine => pair / (first * second).sqrt(),
+            Association::Lift => pair * recipes / (first * second),
+        }
+    }
+}
+
+/// Options for removing uninformative ingredients and edges from a `Relation`.
+/// The defaults keep everything.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Ingredients found in fewer recipes are removed
+    pub min_count: usize,
+    /// Ingredients found in more recipes are removed
+    pub max_count: Option<usize>,
+    /// Ingredients found in more than this fraction of the recipes are removed
+    pub max_fraction: Option<f64>,
+    /// Ingredients that are always removed, such as salt and water
+    pub stoplist: BTreeSet<String>,
+    /// Co-occurrences between two different ingredients with a lower count are
+    /// removed
+    pub min_edge_weight: usize,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Relation {
     // Key: name Value: ID, Count
     ingredient_map: BTreeMap<String, (usize, usize)>,
     // Indexed on ID
     ingredients_list: Vec<String>,
+    #[serde(with = "crate::snapshot::csr")]
     ingredient_ingredient: SparseMatrix<usize>,
     recipe_count: usize,
 }
@@ -29,6 +89,60 @@ impl Relation {
         self.recipe_count
     }
 
+    /// Builds the relation from a binary recipe x ingredient matrix, R, as
+    /// R^T * R
+    pub(crate) fn from_recipe_ingredient(
+        recipe_ingredient: &SparseMatrix<usize>,
+        ingredient_map: BTreeMap<String, (usize, usize)>,
+        ingredients_list: Vec<String>,
+    ) -> Relation {
+        let ingredient_ingredient = &recipe_ingredient.transpose() * recipe_ingredient;
+
+        Relation {
+            ingredient_map,
+            ingredients_list,
+            ingredient_ingredient,
+            recipe_count: recipe_ingredient.num_rows(),
+        }
+    }
+
+    /// Builds the relation from an already computed co-occurrence matrix indexed in
+    /// the order of `ingredients_list`. The counts are read off the diagonal,
+    /// ingredients without a diagonal entry get a count of 0.
+    pub(crate) fn from_matrix(
+        ingredients_list: Vec<String>,
+        ingredient_ingredient: SparseMatrix<usize>,
+        recipe_count: usize,
+    ) -> Relation {
+        let mut counts = vec![0; ingredients_list.len()];
+        for (i, _, count) in triplets(&ingredient_ingredient).filter(|(i, j, _)| i == j) {
+            counts[i] = count;
+        }
+
+        let ingredient_map = ingredients_list
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), (id, counts[id])))
+            .collect();
+
+        Relation {
+            ingredient_map,
+            ingredients_list,
+            ingredient_ingredient,
+            recipe_count,
+        }
+    }
+
+    /// Same ingredients and counts with a different co-occurrence matrix
+    pub(crate) fn with_matrix(&self, ingredient_ingredient: SparseMatrix<usize>) -> Relation {
+        Relation {
+            ingredient_map: self.ingredient_map.clone(),
+            ingredients_list: self.ingredients_list.clone(),
+            ingredient_ingredient,
+            recipe_count: self.recipe_count,
+        }
+    }
+
     pub fn into_parts(
         self,
     ) -> (
@@ -45,11 +159,133 @@ impl Relation {
         )
     }
 
-    pub fn write_files(&self) {
-        use std::fs;
-        use std::io::prelude::*;
-        let mut file = fs::File::create("temp/ingredient_ingredient.coo").unwrap();
+    /// Number of recipes that contain the ingredient
+    pub fn get_count(&self, ingredient: &str) -> Option<usize> {
+        self.ingredient_map.get(ingredient).map(|(_, count)| *count)
+    }
+
+    /// Number of recipes that contain each ingredient, indexed on ID
+    pub fn get_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.ingredients_list.len()];
+        for (id, count) in self.ingredient_map.values() {
+            counts[*id] = *count;
+        }
+        counts
+    }
+
+    /// The n ingredients found in the most recipes, most frequent first
+    pub fn top_ingredients(&self, n: usize) -> Vec<(String, usize)> {
+        let mut ingredients: Vec<(String, usize)> = self
+            .ingredient_map
+            .iter()
+            .map(|(name, (_, count))| (name.clone(), *count))
+            .collect();
+        ingredients.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ingredients.truncate(n);
+        ingredients
+    }
 
+    /// Key: number of recipes an ingredient is in Value: number of ingredients
+    /// found in exactly that many recipes
+    pub fn frequency_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for (_, count) in self.ingredient_map.values() {
+            *histogram.entry(*count).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Every ingredient found in at most max_count recipes, rarest first
+    pub fn rare_ingredients(&self, max_count: usize) -> Vec<(String, usize)> {
+        let mut ingredients: Vec<(String, usize)> = self
+            .ingredient_map
+            .iter()
+            .filter(|(_, (_, count))| *count <= max_count)
+            .map(|(name, (_, count))| (name.clone(), *count))
+            .collect();
+        ingredients.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        ingredients
+    }
+
+    /// Ingredient x ingredient matrix of the association measure for every pair of
+    /// ingredients that appear together in at least one recipe. The diagonal is
+    /// left out.
+    pub fn association_matrix(&self, measure: Association) -> SparseMatrix<f64> {
+        let counts: Vec<f64> = self.get_counts().into_iter().map(|x| x as f64).collect();
+        let recipes = self.recipe_count as f64;
+        let matrix_elements = triplets(&self.ingredient_ingredient)
+            .filter(|(i, j, _)| i != j)
+            .map(|(i, j, count)| {
+                let score = measure.score(count as f64, counts[i], counts[j], recipes);
+                MatrixElement::new(i, j, score)
+            })
+            .collect();
+
+        let n = self.ingredients_list.len();
+        build_matrix(n, n, matrix_elements)
+    }
+
+    /// Builds a new relation without the ingredients and edges removed by the
+    /// options. The remaining ingredients are given new consecutive IDs in the same
+    /// order as before.
+    pub fn prune(&self, options: &PruneOptions) -> Relation {
+        let recipes = self.recipe_count as f64;
+        let mut new_ids = vec![None; self.ingredients_list.len()];
+        let mut ingredient_map = BTreeMap::new();
+        let mut ingredients_list = vec![];
+
+        for (id, name) in self.ingredients_list.iter().enumerate() {
+            let count = self.ingredient_map[name].1;
+            let keep = count >= options.min_count
+                && options.max_count.is_none_or(|max| count <= max)
+                && options
+                    .max_fraction
+                    .is_none_or(|max| count as f64 <= max * recipes)
+                && !options.stoplist.contains(name);
+
+            if keep {
+                new_ids[id] = Some(ingredients_list.len());
+                ingredient_map.insert(name.clone(), (ingredients_list.len(), count));
+                ingredients_list.push(name.clone());
+            }
+        }
+
+        let matrix_elements = triplets(&self.ingredient_ingredient)
+            .filter(|(i, j, weight)| i == j || *weight >= options.min_edge_weight)
+            .filter_map(|(i, j, weight)| match (new_ids[i], new_ids[j]) {
+                (Some(i), Some(j)) => Some(MatrixElement::new(i, j, weight)),
+                _ => None,
+            })
+            .collect();
+
+        let n = ingredients_list.len();
+        let ingredient_ingredient = build_matrix(n, n, matrix_elements);
+
+        Relation {
+            ingredient_map,
+            ingredients_list,
+            ingredient_ingredient,
+            recipe_count: self.recipe_count,
+        }
+    }
+
+    /// Writes ingredient_ingredient.coo and ingredient_labels.txt into an existing
+    /// directory, see `Relation::write_coolist` and `Relation::write_labels`. Read
+    /// them back with `import::read_relation_files`.
+    pub fn write_files<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+
+        let path = dir.join("ingredient_ingredient.coo");
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        self.write_coolist(BufWriter::new(file))?;
+
+        let path = dir.join("ingredient_labels.txt");
+        let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+        self.write_labels(BufWriter::new(file))
+    }
+
+    /// Writes the off diagonal co-occurrences as `i j count` lines
+    pub fn write_coolist<W: Write>(&self, mut writer: W) -> Result<()> {
         let coolist: String = self
             .ingredient_ingredient
             .elements()
@@ -58,11 +294,21 @@ impl Relation {
             .collect::<Vec<String>>()
             .join("\n");
 
-        file.write_all(coolist.as_bytes()).unwrap();
+        writer.write_all(coolist.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        let mut file = fs::File::create("temp/ingredient_labels.txt").unwrap();
-        let labels: String = self.ingredients_list.join("\n");
-        file.write_all(labels.as_bytes()).unwrap();
+    /// Writes a `# recipes <recipe count>` header followed by one `name<TAB>count`
+    /// line per ingredient, in ID order. The counts are the diagonal left out of
+    /// `Relation::write_coolist`.
+    pub fn write_labels<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "# recipes {}", self.recipe_count)?;
+        for name in self.ingredients_list.iter() {
+            writeln!(writer, "{}\t{}", name, self.ingredient_map[name].1)?;
+        }
+        writer.flush()?;
+        Ok(())
     }
 }
 
@@ -79,10 +325,16 @@ pub async fn recipe_ingredient(
     let recipe_count = recipes.len();
 
     for (i, (_, ingredients)) in recipes.into_iter().enumerate() {
+        // counts are the number of recipes containing an ingredient so repeats
+        // within one recipe are skipped
+        let mut seen = BTreeSet::new();
         for ingredient in ingredients.into_iter() {
+            if !seen.insert(ingredient) {
+                continue;
+            }
             let j = match ingredient_map.get_mut(ingredient) {
-                Some((id, mut _count)) => {
-                    _count += 1;
+                Some((id, count)) => {
+                    *count += 1;
                     *id
                 }
                 None => {
@@ -96,22 +348,68 @@ pub async fn recipe_ingredient(
         }
     }
 
-    // TODO: fix error in matrixlab so no unwrap
-    let recipe_ingredient =
-        SparseMatrix::new(recipe_count, ingredients_list.len(), recipe_ingredient).unwrap();
+    let recipe_ingredient = build_matrix(recipe_count, ingredients_list.len(), recipe_ingredient);
 
     Ok((recipe_ingredient, ingredient_map, ingredients_list))
 }
 
 pub async fn make_relation(recipes: &Vec<(i32, Vec<String>)>) -> Result<Relation> {
-    let recipe_count = recipes.len();
     let (recipe_ingredient, ingredient_map, ingredients_list) = recipe_ingredient(&recipes).await?;
-    let ingredient_ingredient = &recipe_ingredient.transpose() * &recipe_ingredient;
 
-    Ok(Relation {
+    Ok(Relation::from_recipe_ingredient(
+        &recipe_ingredient,
         ingredient_map,
         ingredients_list,
-        ingredient_ingredient,
-        recipe_count,
-    })
+    ))
+}
+
+/// Same as `make_relation` followed by `Relation::prune`
+pub async fn make_pruned_relation(
+    recipes: &Vec<(i32, Vec<String>)>,
+    options: &PruneOptions,
+) -> Result<Relation> {
+    Ok(make_relation(recipes).await?.prune(options))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Shared by the tests of every module that needs a small relation. flour is
+    // repeated in the second recipe but only counted once.
+    pub(crate) async fn relation() -> Relation {
+        let recipes = vec![
+            (1, vec!["flour".into(), "butter".into(), "sugar".into()]),
+            (2, vec!["flour".into(), "eggs".into(), "flour".into()]),
+            (3, vec!["flour".into(), "butter".into(), "salt".into()]),
+        ];
+        make_relation(&recipes).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_are_recipes_containing_each_ingredient() {
+        let relation = relation().await;
+
+        assert_eq!(
+            relation.get_ingredient_list(),
+            &vec!["flour", "butter", "sugar", "eggs", "salt"]
+        );
+        assert_eq!(relation.get_counts(), vec![3, 2, 1, 1, 1]);
+        assert_eq!(
+            relation.top_ingredients(2),
+            vec![("flour".to_string(), 3), ("butter".to_string(), 2)]
+        );
+        assert_eq!(
+            relation.frequency_histogram(),
+            vec![(1, 3), (2, 1), (3, 1)].into_iter().collect()
+        );
+        assert_eq!(
+            relation.rare_ingredients(1),
+            vec![
+                ("eggs".to_string(), 1),
+                ("salt".to_string(), 1),
+                ("sugar".to_string(), 1)
+            ]
+        );
+    }
 }
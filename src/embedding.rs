@@ -0,0 +1,482 @@
+use crate::co_occurrence::Relation;
+use crate::graph::{triplets, Graph};
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+// On disk form of an `Embedding` for `Embedding::save` and `Embedding::load`
+#[derive(Serialize, Deserialize)]
+struct SavedEmbedding {
+    labels: Vec<String>,
+    coordinates: Vec<Vec<f64>>,
+}
+
+/// Coordinates of every ingredient of a `Relation`, indexed on ingredient ID
+pub struct Embedding {
+    labels: Vec<String>,
+    coordinates: Vec<Vec<f64>>,
+    // Key: label Value: ID
+    index: BTreeMap<String, usize>,
+}
+
+impl Embedding {
+    pub fn new(labels: Vec<String>, coordinates: Vec<Vec<f64>>) -> Result<Embedding> {
+        if labels.len() != coordinates.len() {
+            return Err(anyhow!(
+                "{} labels for {} coordinate vectors",
+                labels.len(),
+                coordinates.len()
+            ));
+        }
+        let dimensions = coordinates.first().map_or(0, |x| x.len());
+        if let Some(id) = coordinates.iter().position(|x| x.len() != dimensions) {
+            return Err(anyhow!(
+                "{} has {} dimensions, expected {}",
+                labels[id],
+                coordinates[id].len(),
+                dimensions
+            ));
+        }
+        if let Some(id) = coordinates
+            .iter()
+            .position(|x| x.iter().any(|x| !x.is_finite()))
+        {
+            return Err(anyhow!(
+                "{} has a non-finite coordinate in vector {}",
+                labels[id],
+                id + 1
+            ));
+        }
+
+        let index = labels
+            .iter()
+            .enumerate()
+            .map(|(id, label)| (label.clone(), id))
+            .collect();
+
+        Ok(Embedding {
+            labels,
+            coordinates,
+            index,
+        })
+    }
+
+    pub fn get_labels(&self) -> &Vec<String> {
+        &self.labels
+    }
+
+    pub fn get_coordinates(&self) -> &Vec<Vec<f64>> {
+        &self.coordinates
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.coordinates.first().map_or(0, |x| x.len())
+    }
+
+    /// Coordinates of a single ingredient
+    pub fn get(&self, label: &str) -> Option<&[f64]> {
+        self.index
+            .get(label)
+            .map(|id| self.coordinates[*id].as_slice())
+    }
+
+    /// Writes the coordinates one ingredient per line in ID order, the same layout
+    /// graph-embed used to print
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        for vector in self.coordinates.iter() {
+            let line: Vec<String> = vector.iter().map(|x| x.to_string()).collect();
+            writeln!(writer, "{}", line.join(" "))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Parses coordinates written by `Embedding::write` or by graph-embed, one
+    /// whitespace separated vector per line in the order of `labels`. Pass
+    /// `Relation::get_ingredient_list` for the labels of the relation that was
+    /// embedded.
+    pub fn read<R: Read>(mut reader: R, labels: Vec<String>) -> Result<Embedding> {
+        let mut data = String::new();
+        reader.read_to_string(&mut data)?;
+
+        let coordinates = data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .enumerate()
+            .map(|(id, (i, line))| {
+                let vector = line
+                    .split_whitespace()
+                    .map(|x| x.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+                if vector.iter().any(|x| !x.is_finite()) {
+                    return Err(anyhow!(
+                        "line {}: {} has a non-finite coordinate",
+                        i + 1,
+                        labels.get(id).map_or("an extra vector", |x| x.as_str())
+                    ));
+                }
+                Ok(vector)
+            })
+            .collect::<Result<Vec<Vec<f64>>>>()?;
+
+        Embedding::new(labels, coordinates)
+    }
+
+    /// Saves the labels and coordinates as JSON
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        let saved = SavedEmbedding {
+            labels: self.labels.clone(),
+            coordinates: self.coordinates.clone(),
+        };
+        serde_json::to_writer(writer, &saved)?;
+        Ok(())
+    }
+
+    /// Loads an embedding saved with `Embedding::save`
+    pub fn load<R: Read>(reader: R) -> Result<Embedding> {
+        let saved: SavedEmbedding = serde_json::from_reader(reader)?;
+        Embedding::new(saved.labels, saved.coordinates)
+    }
+
+    /// Cosine similarity between two ingredients
+    pub fn similarity(&self, first: &str, second: &str) -> Result<f64> {
+        Ok(cosine(self.vector(first)?, self.vector(second)?))
+    }
+
+    /// The k ingredients closest to an ingredient by cosine similarity along with
+    /// their similarity, most similar first
+    pub fn nearest(&self, label: &str, k: usize) -> Result<Vec<(String, f64)>> {
+        let vector = self.vector(label)?;
+        Ok(self.nearest_to(vector, &[label], k))
+    }
+
+    /// Solves `a` is to `b` as `c` is to ? by searching for the ingredients closest
+    /// to b - a + c, leaving out the three query ingredients. "beef is to stew as X
+    /// is to stir-fry" is `analogy("stew", "beef", "stir-fry", k)`.
+    pub fn analogy(&self, a: &str, b: &str, c: &str, k: usize) -> Result<Vec<(String, f64)>> {
+        let (a_vector, b_vector, c_vector) = (self.vector(a)?, self.vector(b)?, self.vector(c)?);
+        let target: Vec<f64> = (0..self.dimensions())
+            .map(|d| b_vector[d] - a_vector[d] + c_vector[d])
+            .collect();
+
+        Ok(self.nearest_to(&target, &[a, b, c], k))
+    }
+
+    fn vector(&self, label: &str) -> Result<&[f64]> {
+        self.get(label)
+            .ok_or_else(|| anyhow!("{} is not in the embedding", label))
+    }
+
+    fn nearest_to(&self, target: &[f64], exclude: &[&str], k: usize) -> Vec<(String, f64)> {
+        let mut scores: Vec<(String, f64)> = self
+            .labels
+            .iter()
+            .zip(self.coordinates.iter())
+            .filter(|(label, _)| !exclude.contains(&label.as_str()))
+            .map(|(label, vector)| (label.clone(), cosine(target, vector)))
+            .collect();
+
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(k);
+        scores
+    }
+}
+
+// Zero when either vector has no length
+fn cosine(first: &[f64], second: &[f64]) -> f64 {
+    let dot: f64 = first.iter().zip(second.iter()).map(|(x, y)| x * y).sum();
+    let norms = first.iter().map(|x| x * x).sum::<f64>().sqrt()
+        * second.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norms > 0.0 {
+        dot / norms
+    } else {
+        0.0
+    }
+}
+
+/// Common interface over the embedding algorithms. The diagonal of the
+/// co-occurrence matrix is ignored so only ingredients appearing together matter.
+pub trait Embedder {
+    fn embed(&self, relation: &Relation) -> Embedding;
+}
+
+/// Laplacian eigenmaps. Ingredients are placed by the eigenvectors of the
+/// normalized Laplacian with the smallest non-zero eigenvalues, so ingredients that
+/// co-occur often end up close together. On a disconnected graph the first
+/// coordinates separate the components.
+pub struct LaplacianEigenmaps {
+    pub dimensions: usize,
+    /// Number of subspace iterations used to find the eigenvectors
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for LaplacianEigenmaps {
+    fn default() -> Self {
+        Self {
+            dimensions: 2,
+            iterations: 300,
+            seed: 0,
+        }
+    }
+}
+
+impl Embedder for LaplacianEigenmaps {
+    fn embed(&self, relation: &Relation) -> Embedding {
+        let graph = off_diagonal_graph(relation);
+        let n = graph.num_nodes();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let sqrt_degrees: Vec<f64> = (0..n).map(|node| graph.degree(node).sqrt()).collect();
+        let norm = sqrt_degrees.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let trivial: Vec<f64> = sqrt_degrees
+            .iter()
+            .map(|x| if norm > 0.0 { x / norm } else { 0.0 })
+            .collect();
+
+        // Subspace iteration on (I + D^-1/2 A D^-1/2) / 2, the same operator
+        // `spectral` uses for the Fiedler vector, keeping the block orthogonal to
+        // the trivial eigenvector and to itself.
+        let mut block: Vec<Vec<f64>> = (0..self.dimensions)
+            .map(|_| (0..n).map(|_| rng.gen::<f64>() - 0.5).collect())
+            .collect();
+        orthonormalize(&mut block, &trivial);
+
+        for _ in 0..self.iterations {
+            block = block
+                .iter()
+                .map(|vector| {
+                    let mut next: Vec<f64> = vector.iter().map(|x| x / 2.0).collect();
+                    for node in 0..n {
+                        if sqrt_degrees[node] == 0.0 {
+                            continue;
+                        }
+                        for &(neighbor, weight) in graph.neighbors(node) {
+                            next[node] += weight * vector[neighbor]
+                                / (2.0 * sqrt_degrees[node] * sqrt_degrees[neighbor]);
+                        }
+                    }
+                    next
+                })
+                .collect();
+            orthonormalize(&mut block, &trivial);
+        }
+
+        let coordinates = (0..n)
+            .map(|node| {
+                block
+                    .iter()
+                    .map(|vector| {
+                        if sqrt_degrees[node] > 0.0 {
+                            vector[node] / sqrt_degrees[node]
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Embedding::new(relation.get_ingredient_list().clone(), coordinates).unwrap()
+    }
+}
+
+/// DeepWalk style embedding. Weighted random walks over the co-occurrence graph
+/// are treated as sentences and a skip-gram model with negative sampling learns a
+/// vector for every ingredient, equivalent to node2vec with p = q = 1.
+pub struct RandomWalk {
+    pub dimensions: usize,
+    pub walks_per_node: usize,
+    pub walk_length: usize,
+    /// Number of vertices on either side of a vertex in a walk counted as context
+    pub window: usize,
+    /// Number of negative samples for every context pair
+    pub negative: usize,
+    /// Passes over the walks
+    pub epochs: usize,
+    /// Starting learning rate, decayed linearly towards zero
+    pub learning_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for RandomWalk {
+    fn default() -> Self {
+        Self {
+            dimensions: 32,
+            walks_per_node: 10,
+            walk_length: 40,
+            window: 5,
+            negative: 5,
+            epochs: 1,
+            learning_rate: 0.025,
+            seed: 0,
+        }
+    }
+}
+
+impl Embedder for RandomWalk {
+    fn embed(&self, relation: &Relation) -> Embedding {
+        let graph = off_diagonal_graph(relation);
+        let n = graph.num_nodes();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let mut walks = vec![];
+        for _ in 0..self.walks_per_node {
+            for start in 0..n {
+                walks.push(self.walk(&graph, start, &mut rng));
+            }
+        }
+
+        // negative samples are drawn proportional to frequency^0.75 like word2vec
+        let mut frequencies = vec![0.0; n];
+        for node in walks.iter().flatten() {
+            frequencies[*node] += 1.0;
+        }
+        let noise = cumulative(frequencies.iter().map(|x: &f64| x.powf(0.75)));
+
+        let d = self.dimensions;
+        let mut input: Vec<Vec<f64>> = (0..n)
+            .map(|_| {
+                (0..d)
+                    .map(|_| (rng.gen::<f64>() - 0.5) / d as f64)
+                    .collect()
+            })
+            .collect();
+        let mut output = vec![vec![0.0; d]; n];
+
+        let total_steps = (self.epochs * walks.len()).max(1) as f64;
+        let mut step = 0.0;
+        let mut gradient = vec![0.0; d];
+
+        for _ in 0..self.epochs {
+            walks.shuffle(&mut rng);
+            for walk in walks.iter() {
+                let learning_rate = (self.learning_rate * (1.0 - step / total_steps))
+                    .max(self.learning_rate * 1e-4);
+                step += 1.0;
+
+                for (position, &center) in walk.iter().enumerate() {
+                    let start = position.saturating_sub(self.window);
+                    let end = (position + self.window + 1).min(walk.len());
+
+                    for &context in walk[start..end].iter() {
+                        if context == center {
+                            continue;
+                        }
+                        gradient.iter_mut().for_each(|x| *x = 0.0);
+
+                        for sample in 0..=self.negative {
+                            let (target, label) = if sample == 0 {
+                                (context, 1.0)
+                            } else {
+                                let target = sample_from(&noise, &mut rng);
+                                if target == context {
+                                    continue;
+                                }
+                                (target, 0.0)
+                            };
+
+                            let dot: f64 = input[center]
+                                .iter()
+                                .zip(output[target].iter())
+                                .map(|(x, y)| x * y)
+                                .sum();
+                            let g = (label - sigmoid(dot)) * learning_rate;
+
+                            for k in 0..d {
+                                gradient[k] += g * output[target][k];
+                                output[target][k] += g * input[center][k];
+                            }
+                        }
+
+                        for k in 0..d {
+                            input[center][k] += gradient[k];
+                        }
+                    }
+                }
+            }
+        }
+
+        Embedding::new(relation.get_ingredient_list().clone(), input).unwrap()
+    }
+}
+
+impl RandomWalk {
+    // Each step moves to a neighbor with probability proportional to the number of
+    // recipes they share
+    fn walk(&self, graph: &Graph, start: usize, rng: &mut StdRng) -> Vec<usize> {
+        let mut walk = vec![start];
+        let mut node = start;
+
+        while walk.len() < self.walk_length {
+            let neighbors = graph.neighbors(node);
+            if neighbors.is_empty() {
+                break;
+            }
+            let weights = cumulative(neighbors.iter().map(|(_, w)| *w));
+            node = neighbors[sample_from(&weights, rng)].0;
+            walk.push(node);
+        }
+
+        walk
+    }
+}
+
+// The co-occurrence graph without the self loops holding each ingredient's count
+fn off_diagonal_graph(relation: &Relation) -> Graph {
+    let mut adjacency = vec![vec![]; relation.get_ingredient_list().len()];
+    for (i, j, count) in triplets(relation.get_matrix()).filter(|(i, j, _)| i != j) {
+        adjacency[i].push((j, count as f64));
+    }
+    Graph::from_adjacency(adjacency)
+}
+
+// Modified Gram-Schmidt, removing the trivial eigenvector from every vector first
+fn orthonormalize(block: &mut [Vec<f64>], trivial: &[f64]) {
+    for k in 0..block.len() {
+        let (previous, rest) = block.split_at_mut(k);
+        let vector = &mut rest[0];
+
+        for other in std::iter::once(trivial).chain(previous.iter().map(|x| x.as_slice())) {
+            let projection: f64 = vector.iter().zip(other.iter()).map(|(x, y)| x * y).sum();
+            for (x, y) in vector.iter_mut().zip(other.iter()) {
+                *x -= projection * y;
+            }
+        }
+
+        let length = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if length > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= length);
+        }
+    }
+}
+
+fn cumulative<I: Iterator<Item = f64>>(weights: I) -> Vec<f64> {
+    weights
+        .scan(0.0, |total, weight| {
+            *total += weight;
+            Some(*total)
+        })
+        .collect()
+}
+
+// Index drawn with probability proportional to the gaps in a cumulative sum
+fn sample_from(cumulative: &[f64], rng: &mut StdRng) -> usize {
+    let total = cumulative.last().copied().unwrap_or(0.0);
+    let target = rng.gen::<f64>() * total;
+    let index = match cumulative.binary_search_by(|x| x.partial_cmp(&target).unwrap()) {
+        Ok(i) => i + 1,
+        Err(i) => i,
+    };
+    index.min(cumulative.len() - 1)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
@@ -1,11 +1,13 @@
+use crate::graph::build_matrix;
+use anyhow::{anyhow, Result};
 use itertools::Itertools;
 use matrixlab::matrix::sparse::SparseMatrix;
 use matrixlab::MatrixElement;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::Path;
+use std::io::Write;
 
+#[derive(Serialize, Deserialize)]
 enum ExpandedVertex {
     // has only an associated ingredient ID
     IngredientHub(usize),
@@ -33,8 +35,10 @@ impl ExpandedVertex {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct ExpandedIngredientRelation {
     vertices: Vec<ExpandedVertex>,
+    #[serde(with = "crate::snapshot::edge_list")]
     edges: BTreeMap<(usize, usize), usize>,
 }
 
@@ -153,19 +157,33 @@ impl ExpandedIngredientRelation {
         self.edges.len()
     }
 
-    pub async fn build_coolist(&self) {
+    /// Writes the edges as `i j weight` lines with i < j
+    pub async fn build_coolist<W: Write>(&self, mut writer: W) -> Result<()> {
         let coolist = self
             .edges
             .iter()
             .map(|((i, j), weight)| format!("{} {} {}", i, j, weight))
             .join("\n");
 
-        let path = Path::new("temp/expanded_coolist");
-        let mut temp_file = File::create(&path).unwrap();
-        temp_file.write_all(coolist.as_bytes()).unwrap();
+        writer.write_all(coolist.as_bytes())?;
+        writer.flush()?;
+        Ok(())
     }
 
     pub async fn build_adjacency_matrix(&self) -> SparseMatrix<usize> {
+        self.adjacency_matrix()
+    }
+
+    /// Every edge must have i < j and both vertices in range
+    pub(crate) fn check_edges(&self) -> Result<()> {
+        let n = self.number_of_vertices();
+        match self.edges.keys().find(|(i, j)| i >= j || *j >= n) {
+            Some((i, j)) => Err(anyhow!("invalid edge {} {} for {} vertices", i, j, n)),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn adjacency_matrix(&self) -> SparseMatrix<usize> {
         let matrix_elements: Vec<MatrixElement<usize>> = self
             .edges
             .iter()
@@ -179,6 +197,6 @@ impl ExpandedIngredientRelation {
             .collect();
 
         let number_of_vertices = self.number_of_vertices();
-        SparseMatrix::new(number_of_vertices, number_of_vertices, matrix_elements).unwrap()
+        build_matrix(number_of_vertices, number_of_vertices, matrix_elements)
     }
 }
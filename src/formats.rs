@@ -0,0 +1,247 @@
+use crate::co_occurrence::Relation;
+use crate::graph::triplets;
+use anyhow::{anyhow, Result};
+use matrixlab::matrix::sparse::SparseMatrix;
+use std::io::Write;
+
+/// GraphML for Cytoscape, Gephi or networkx. Every ingredient is a node with its
+/// label, the number of recipes it is in and, if given, its community ID indexed
+/// on ingredient ID. The communities must come from partitioning
+/// `Relation::get_matrix` directly, for example `Louvain.partition(..).communities`,
+/// since a `Hierarchy` partitions the expanded graph whose vertices are recipe and
+/// ingredient pairs. Every pair of ingredients that appear together is an
+/// undirected edge weighted by the number of recipes they share.
+pub fn write_graphml<W: Write>(
+    relation: &Relation,
+    communities: Option<&[usize]>,
+    mut writer: W,
+) -> Result<()> {
+    check_communities(relation, communities)?;
+    let counts = relation.get_counts();
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <key id="frequency" for="node" attr.name="frequency" attr.type="int"/>"#
+    )?;
+    if communities.is_some() {
+        writeln!(
+            writer,
+            r#"  <key id="community" for="node" attr.name="community" attr.type="int"/>"#
+        )?;
+    }
+    writeln!(
+        writer,
+        r#"  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <graph id="ingredients" edgedefault="undirected">"#
+    )?;
+
+    for (id, label) in relation.get_ingredient_list().iter().enumerate() {
+        writeln!(writer, r#"    <node id="n{}">"#, id)?;
+        writeln!(
+            writer,
+            r#"      <data key="label">{}</data>"#,
+            escape_xml(label)
+        )?;
+        writeln!(
+            writer,
+            r#"      <data key="frequency">{}</data>"#,
+            counts[id]
+        )?;
+        if let Some(communities) = communities {
+            writeln!(
+                writer,
+                r#"      <data key="community">{}</data>"#,
+                communities[id]
+            )?;
+        }
+        writeln!(writer, "    </node>")?;
+    }
+
+    for (i, j, weight) in edges(relation) {
+        writeln!(
+            writer,
+            r#"    <edge source="n{}" target="n{}"><data key="weight">{}</data></edge>"#,
+            i, j, weight
+        )?;
+    }
+
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// GEXF 1.3 for Gephi with the same nodes, attributes and edges as `write_graphml`
+pub fn write_gexf<W: Write>(
+    relation: &Relation,
+    communities: Option<&[usize]>,
+    mut writer: W,
+) -> Result<()> {
+    check_communities(relation, communities)?;
+    let counts = relation.get_counts();
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <graph mode="static" defaultedgetype="undirected">"#
+    )?;
+    writeln!(writer, r#"    <attributes class="node">"#)?;
+    writeln!(
+        writer,
+        r#"      <attribute id="frequency" title="frequency" type="integer"/>"#
+    )?;
+    if communities.is_some() {
+        writeln!(
+            writer,
+            r#"      <attribute id="community" title="community" type="integer"/>"#
+        )?;
+    }
+    writeln!(writer, "    </attributes>")?;
+
+    writeln!(writer, "    <nodes>")?;
+    for (id, label) in relation.get_ingredient_list().iter().enumerate() {
+        writeln!(
+            writer,
+            r#"      <node id="{}" label="{}">"#,
+            id,
+            escape_xml(label)
+        )?;
+        writeln!(writer, "        <attvalues>")?;
+        writeln!(
+            writer,
+            r#"          <attvalue for="frequency" value="{}"/>"#,
+            counts[id]
+        )?;
+        if let Some(communities) = communities {
+            writeln!(
+                writer,
+                r#"          <attvalue for="community" value="{}"/>"#,
+                communities[id]
+            )?;
+        }
+        writeln!(writer, "        </attvalues>")?;
+        writeln!(writer, "      </node>")?;
+    }
+    writeln!(writer, "    </nodes>")?;
+
+    writeln!(writer, "    <edges>")?;
+    for (edge, (i, j, weight)) in edges(relation).enumerate() {
+        writeln!(
+            writer,
+            r#"      <edge id="{}" source="{}" target="{}" weight="{}"/>"#,
+            edge, i, j, weight
+        )?;
+    }
+    writeln!(writer, "    </edges>")?;
+
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</gexf>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Undirected Graphviz graph with the ingredient names as node labels and the
+/// shared recipe counts as edge weights
+pub fn write_dot<W: Write>(relation: &Relation, mut writer: W) -> Result<()> {
+    writeln!(writer, "graph ingredients {{")?;
+    for (id, label) in relation.get_ingredient_list().iter().enumerate() {
+        writeln!(writer, "  {} [label=\"{}\"];", id, escape_dot(label))?;
+    }
+    for (i, j, weight) in edges(relation) {
+        writeln!(writer, "  {} -- {} [weight={}];", i, j, weight)?;
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Matrix Market coordinate format, readable by scipy.io.mmread. Every stored
+/// entry is written, including the diagonal, with 1-based indices.
+pub fn write_matrix_market<W: Write>(matrix: &SparseMatrix<usize>, mut writer: W) -> Result<()> {
+    writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
+    writeln!(
+        writer,
+        "{} {} {}",
+        matrix.num_rows(),
+        matrix.num_columns(),
+        matrix.get_data().len()
+    )?;
+    for (i, j, value) in triplets(matrix) {
+        writeln!(writer, "{} {} {}", i + 1, j + 1, value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Edge list with a `source,target,weight` header and the ingredient names as the
+/// source and target, each undirected edge written once
+pub fn write_edge_list_csv<W: Write>(relation: &Relation, mut writer: W) -> Result<()> {
+    let labels = relation.get_ingredient_list();
+
+    writeln!(writer, "source,target,weight")?;
+    for (i, j, weight) in edges(relation) {
+        writeln!(
+            writer,
+            "{},{},{}",
+            escape_csv(&labels[i]),
+            escape_csv(&labels[j]),
+            weight
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// The upper triangle of the co-occurrence matrix without the diagonal
+fn edges(relation: &Relation) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+    triplets(relation.get_matrix()).filter(|(i, j, _)| i < j)
+}
+
+fn check_communities(relation: &Relation, communities: Option<&[usize]>) -> Result<()> {
+    let n = relation.get_ingredient_list().len();
+    match communities {
+        Some(communities) if communities.len() != n => Err(anyhow!(
+            "{} community IDs for {} ingredients",
+            communities.len(),
+            n
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Quotes fields the same way `source::split_csv_line` reads them
+fn escape_csv(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,226 @@
+use matrixlab::matrix::sparse::SparseMatrix;
+use matrixlab::{Element, MatrixElement};
+
+/// Weighted undirected graph stored as adjacency lists. Self loops are kept in the
+/// adjacency lists and count once towards the degree of their vertex, which matches
+/// the symmetric adjacency matrices produced by `ExpandedIngredientRelation`.
+pub(crate) struct Graph {
+    adjacency: Vec<Vec<(usize, f64)>>,
+    degrees: Vec<f64>,
+    // Sum of all the entries in the adjacency matrix (2m)
+    total_weight: f64,
+}
+
+impl Graph {
+    /// Builds the graph from a symmetric adjacency matrix
+    pub fn from_matrix(matrix: &SparseMatrix<usize>) -> Graph {
+        let rows = matrix.get_rows();
+        let columns = matrix.get_columns();
+        let data = matrix.get_data();
+
+        let adjacency = (0..matrix.num_rows())
+            .map(|i| {
+                (rows[i]..rows[i + 1])
+                    .map(|k| (columns[k], data[k] as f64))
+                    .collect()
+            })
+            .collect();
+
+        Graph::from_adjacency(adjacency)
+    }
+
+    pub fn from_adjacency(adjacency: Vec<Vec<(usize, f64)>>) -> Graph {
+        let degrees: Vec<f64> = adjacency
+            .iter()
+            .map(|neighbors| neighbors.iter().map(|(_, w)| w).sum())
+            .collect();
+        let total_weight = degrees.iter().sum();
+
+        Graph {
+            adjacency,
+            degrees,
+            total_weight,
+        }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    pub fn neighbors(&self, node: usize) -> &[(usize, f64)] {
+        &self.adjacency[node]
+    }
+
+    pub fn degree(&self, node: usize) -> f64 {
+        self.degrees[node]
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.total_weight
+    }
+
+    /// Collapses every community into a single vertex. Edges inside of a community
+    /// become a self loop on the new vertex so degrees and modularity are preserved.
+    pub fn aggregate(&self, membership: &[usize], num_communities: usize) -> Graph {
+        let mut adjacency: Vec<Vec<(usize, f64)>> = vec![vec![]; num_communities];
+        let mut weights = vec![0.0; num_communities];
+        let mut touched = vec![];
+        let mut members: Vec<Vec<usize>> = vec![vec![]; num_communities];
+
+        for (node, &community) in membership.iter().enumerate() {
+            members[community].push(node);
+        }
+
+        for (community, nodes) in members.iter().enumerate() {
+            for &node in nodes {
+                for &(neighbor, weight) in self.neighbors(node) {
+                    let other = membership[neighbor];
+                    if weights[other] == 0.0 {
+                        touched.push(other);
+                    }
+                    weights[other] += weight;
+                }
+            }
+            touched.sort_unstable();
+            for &other in touched.iter() {
+                adjacency[community].push((other, weights[other]));
+                weights[other] = 0.0;
+            }
+            touched.clear();
+        }
+
+        Graph::from_adjacency(adjacency)
+    }
+
+    /// Induced subgraph on the given vertices, reindexed in the order given
+    pub fn subgraph(&self, nodes: &[usize]) -> Graph {
+        let mut index = vec![usize::MAX; self.num_nodes()];
+        for (new, &node) in nodes.iter().enumerate() {
+            index[node] = new;
+        }
+
+        let adjacency = nodes
+            .iter()
+            .map(|&node| {
+                self.neighbors(node)
+                    .iter()
+                    .filter(|(neighbor, _)| index[*neighbor] != usize::MAX)
+                    .map(|(neighbor, weight)| (index[*neighbor], *weight))
+                    .collect()
+            })
+            .collect();
+
+        Graph::from_adjacency(adjacency)
+    }
+
+    /// Modularity of a partition of the graph, see Newman and Girvan. A resolution
+    /// above 1 favors smaller communities.
+    pub fn modularity(&self, membership: &[usize], resolution: f64) -> f64 {
+        if self.total_weight == 0.0 {
+            return 0.0;
+        }
+        let num_communities = membership.iter().max().map_or(0, |x| x + 1);
+        let mut internal = vec![0.0; num_communities];
+        let mut totals = vec![0.0; num_communities];
+
+        for node in 0..self.num_nodes() {
+            let community = membership[node];
+            totals[community] += self.degree(node);
+            for &(neighbor, weight) in self.neighbors(node) {
+                if membership[neighbor] == community {
+                    internal[community] += weight;
+                }
+            }
+        }
+
+        internal
+            .iter()
+            .zip(totals.iter())
+            .map(|(internal, total)| {
+                let fraction = total / self.total_weight;
+                internal / self.total_weight - resolution * fraction * fraction
+            })
+            .sum()
+    }
+}
+
+/// Relabels the communities to be 0..n with no gaps, keeping the order in which
+/// they first appear. Returns the number of communities.
+pub(crate) fn renumber(membership: &mut [usize]) -> usize {
+    let mut labels = vec![usize::MAX; membership.len()];
+    let mut counter = 0;
+
+    for community in membership.iter_mut() {
+        if labels[*community] == usize::MAX {
+            labels[*community] = counter;
+            counter += 1;
+        }
+        *community = labels[*community];
+    }
+
+    counter
+}
+
+/// Builds the n x num_communities matrix P with P[node, community] = 1
+pub(crate) fn interpolation_matrix(
+    membership: &[usize],
+    num_communities: usize,
+) -> SparseMatrix<usize> {
+    let matrix_elements = membership
+        .iter()
+        .enumerate()
+        .map(|(node, community)| MatrixElement::new(node, *community, 1))
+        .collect();
+
+    build_matrix(membership.len(), num_communities, matrix_elements)
+}
+
+/// Builds a sparse matrix from elements that are known to be within its bounds,
+/// which is the only way `SparseMatrix::new` can fail
+pub(crate) fn build_matrix<A: Element>(
+    rows: usize,
+    columns: usize,
+    matrix_elements: Vec<MatrixElement<A>>,
+) -> SparseMatrix<A> {
+    // TODO: fix error in matrixlab so no unwrap
+    SparseMatrix::new(rows, columns, matrix_elements).unwrap()
+}
+
+/// Inverse of `interpolation_matrix`, the community of each row
+pub(crate) fn membership(interpolation_matrix: &SparseMatrix<usize>) -> Vec<usize> {
+    let rows = interpolation_matrix.get_rows();
+    let columns = interpolation_matrix.get_columns();
+
+    (0..interpolation_matrix.num_rows())
+        .map(|i| columns[rows[i]])
+        .collect()
+}
+
+/// Every stored entry of the matrix as (row, column, value)
+pub(crate) fn triplets(
+    matrix: &SparseMatrix<usize>,
+) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+    let rows = matrix.get_rows();
+    let columns = matrix.get_columns();
+    let data = matrix.get_data();
+
+    (0..matrix.num_rows())
+        .flat_map(move |i| (rows[i]..rows[i + 1]).map(move |k| (i, columns[k], data[k])))
+}
+
+// Two 5-cliques, 0..5 and 5..10, joined by the edge 4 - 5
+#[cfg(test)]
+pub(crate) fn two_cliques() -> SparseMatrix<usize> {
+    let mut matrix_elements = vec![];
+    for clique in [0..5, 5..10].iter() {
+        for i in clique.clone() {
+            for j in clique.clone().filter(|j| *j != i) {
+                matrix_elements.push(MatrixElement::new(i, j, 1));
+            }
+        }
+    }
+    matrix_elements.push(MatrixElement::new(4, 5, 1));
+    matrix_elements.push(MatrixElement::new(5, 4, 1));
+
+    build_matrix(10, 10, matrix_elements)
+}
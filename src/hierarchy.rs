@@ -1,14 +1,75 @@
 use crate::expanded::ExpandedIngredientRelation;
+use crate::partition::{level_modularity, Partitioner};
 use anyhow::{anyhow, Result};
 use matrixlab::matrix::sparse::SparseMatrix;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "HierarchyParts")]
 pub struct Hierarchy {
+    #[serde(with = "crate::snapshot::csr_vec")]
     interpolation_matrices: Vec<SparseMatrix<usize>>,
     ingredients_vec: Vec<String>,
     ingredients_map: BTreeMap<String, (usize, usize)>,
+    // Rebuilt from the relation when deserializing
+    #[serde(skip_serializing)]
     ingredient_ingredient: SparseMatrix<usize>,
     relation: ExpandedIngredientRelation,
+    // Indexed the same as the interpolation matrices
+    modularity: Vec<f64>,
+}
+
+// Serialized fields of a Hierarchy
+#[derive(Deserialize)]
+struct HierarchyParts {
+    #[serde(with = "crate::snapshot::csr_vec")]
+    interpolation_matrices: Vec<SparseMatrix<usize>>,
+    ingredients_vec: Vec<String>,
+    ingredients_map: BTreeMap<String, (usize, usize)>,
+    relation: ExpandedIngredientRelation,
+    modularity: Vec<f64>,
+}
+
+impl TryFrom<HierarchyParts> for Hierarchy {
+    type Error = anyhow::Error;
+
+    fn try_from(parts: HierarchyParts) -> Result<Hierarchy> {
+        parts.relation.check_edges()?;
+        let n = parts.relation.number_of_vertices();
+        if let Some(node) = (0..n).find(|node| match parts.relation.get_ingredient_id(*node) {
+            Some(id) => id >= parts.ingredients_vec.len(),
+            None => false,
+        }) {
+            return Err(anyhow!("vertex {} has an unknown ingredient", node));
+        }
+
+        let mut rows = n;
+        for matrix in parts.interpolation_matrices.iter() {
+            if matrix.num_rows() != rows {
+                return Err(anyhow!(
+                    "interpolation matrix has {} rows, expected {}",
+                    matrix.num_rows(),
+                    rows
+                ));
+            }
+            rows = matrix.num_columns();
+        }
+        if parts.modularity.len() != parts.interpolation_matrices.len() {
+            return Err(anyhow!("modularity is missing for some levels"));
+        }
+
+        let ingredient_ingredient = parts.relation.adjacency_matrix();
+        Ok(Self {
+            interpolation_matrices: parts.interpolation_matrices,
+            ingredients_map: parts.ingredients_map,
+            ingredients_vec: parts.ingredients_vec,
+            ingredient_ingredient,
+            relation: parts.relation,
+            modularity: parts.modularity,
+        })
+    }
 }
 
 impl Hierarchy {
@@ -19,12 +80,35 @@ impl Hierarchy {
         relation: ExpandedIngredientRelation,
     ) -> Hierarchy {
         let ingredient_ingredient = relation.build_adjacency_matrix().await;
+        let modularity = level_modularity(&ingredient_ingredient, &interpolation_matrices);
+        Self {
+            interpolation_matrices,
+            ingredients_map,
+            ingredients_vec,
+            ingredient_ingredient,
+            relation,
+            modularity,
+        }
+    }
+
+    /// Builds the hierarchy by partitioning the adjacency matrix of the expanded
+    /// relation with the given strategy
+    pub async fn from_partitioner<P: Partitioner>(
+        partitioner: &P,
+        ingredients_vec: Vec<String>,
+        ingredients_map: BTreeMap<String, (usize, usize)>,
+        relation: ExpandedIngredientRelation,
+    ) -> Hierarchy {
+        let ingredient_ingredient = relation.build_adjacency_matrix().await;
+        let (interpolation_matrices, modularity) =
+            partitioner.partition(&ingredient_ingredient).into_parts();
         Self {
             interpolation_matrices,
             ingredients_map,
             ingredients_vec,
             ingredient_ingredient,
             relation,
+            modularity,
         }
     }
 
@@ -40,6 +124,42 @@ impl Hierarchy {
         self.interpolation_matrices.len() + 1
     }
 
+    /// Modularity of the partition at each level, starting with the finest
+    pub fn modularity(&self) -> &Vec<f64> {
+        &self.modularity
+    }
+
+    // Maps each vertex of the expanded graph onto its community at a level
+    fn partition(&self, level: usize) -> SparseMatrix<usize> {
+        let mut partition = self.interpolation_matrices[0].clone();
+        for i in 1..level {
+            partition = &partition * &self.interpolation_matrices[i];
+        }
+        partition
+    }
+
+    /// The names of the ingredients in each community at a level. An ingredient
+    /// can be in several communities since each recipe's use of it is a separate
+    /// vertex.
+    pub fn ingredient_communities(&self, level: usize) -> Result<Vec<BTreeSet<String>>> {
+        if level > self.interpolation_matrices.len() {
+            return Err(anyhow!("out of range"));
+        }
+        let partition = self.partition(level);
+        let mut communities = vec![BTreeSet::new(); partition.num_columns()];
+
+        for (node, agg, _) in partition.elements() {
+            let ingredient_id = self.relation.get_ingredient_id(node);
+            let recipe_id = self.relation.get_recipe_id(node);
+
+            if let (Some(ingredient_id), Some(_)) = (ingredient_id, recipe_id) {
+                communities[agg].insert(self.ingredients_vec[ingredient_id].clone());
+            }
+        }
+
+        Ok(communities)
+    }
+
     pub fn generate_recipes(
         &self,
         level: usize,
@@ -48,10 +168,7 @@ impl Hierarchy {
         if level > self.interpolation_matrices.len() {
             Err(anyhow!("out of range"))
         } else {
-            let mut partition = self.interpolation_matrices[0].clone();
-            for i in 1..level {
-                partition = &partition * &self.interpolation_matrices[i];
-            }
+            let partition = self.partition(level);
 
             let mut aggregates: Vec<BTreeMap<String, Vec<usize>>> =
                 vec![BTreeMap::new(); partition.num_columns()];
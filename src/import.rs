@@ -0,0 +1,449 @@
+use crate::co_occurrence::Relation;
+use crate::graph::triplets;
+use anyhow::{anyhow, Context, Result};
+use matrixlab::matrix::sparse::SparseMatrix;
+use matrixlab::MatrixElement;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Parses the CSR text written by `recipe_analysis::export`: the number of rows and
+/// columns followed by the row pointers, column indices and values, one number per
+/// line.
+pub fn read_csr<R: Read>(reader: R) -> Result<SparseMatrix<usize>> {
+    let data = read_string(reader)?;
+    let mut lines = numbered_lines(&data);
+    let mut next = |what: &str| -> Result<usize> {
+        let (line, text) = lines
+            .next()
+            .ok_or_else(|| anyhow!("unexpected end of file, expected {}", what))?;
+        parse(text, line, what)
+    };
+
+    let rows = next("number of rows")?;
+    let columns = next("number of columns")?;
+    let indptr = (0..=rows)
+        .map(|_| next("row pointer"))
+        .collect::<Result<Vec<usize>>>()?;
+
+    if indptr[0] != 0 || indptr.windows(2).any(|x| x[0] > x[1]) {
+        return Err(anyhow!("row pointers must start at 0 and never decrease"));
+    }
+    let nnz = indptr[rows];
+    let indices = (0..nnz)
+        .map(|_| next("column index"))
+        .collect::<Result<Vec<usize>>>()?;
+    let values = (0..nnz)
+        .map(|_| next("value"))
+        .collect::<Result<Vec<usize>>>()?;
+
+    if let Some((line, _)) = lines.next() {
+        return Err(anyhow!("line {}: expected end of file", line));
+    }
+
+    let mut matrix_elements = Vec::with_capacity(nnz);
+    for row in 0..rows {
+        for k in indptr[row]..indptr[row + 1] {
+            if indices[k] >= columns {
+                return Err(anyhow!(
+                    "column index {} is out of bounds for {} columns",
+                    indices[k],
+                    columns
+                ));
+            }
+            matrix_elements.push(MatrixElement::new(row, indices[k], values[k]));
+        }
+    }
+
+    new_matrix(rows, columns, matrix_elements)
+}
+
+/// Parses the `i j value` lines written by `Relation::write_coolist`. The matrix is
+/// n x n, or just large enough for the largest index when n is None.
+pub fn read_coolist<R: Read>(reader: R, n: Option<usize>) -> Result<SparseMatrix<usize>> {
+    let triplets = read_triplets(reader)?;
+    let n = size(&triplets, n)?;
+
+    let matrix_elements = triplets
+        .into_iter()
+        .map(|(i, j, value)| MatrixElement::new(i, j, value))
+        .collect();
+    new_matrix(n, n, matrix_elements)
+}
+
+/// Parses the `i j weight` lines written by `ExpandedIngredientRelation::build_coolist`
+/// into a symmetric adjacency matrix, the same matrix as
+/// `ExpandedIngredientRelation::build_adjacency_matrix`. Only one direction of each
+/// edge is in the file so each line becomes two entries. n is the number of
+/// vertices, or just enough for the largest index when None.
+pub fn read_expanded_coolist<R: Read>(reader: R, n: Option<usize>) -> Result<SparseMatrix<usize>> {
+    let triplets = read_triplets(reader)?;
+    let n = size(&triplets, n)?;
+
+    let mut edges = BTreeSet::new();
+    for (i, j, _) in triplets.iter() {
+        if !edges.insert((*i.min(j), *i.max(j))) {
+            return Err(anyhow!("edge {} {} appears more than once", i, j));
+        }
+    }
+
+    let matrix_elements = triplets
+        .into_iter()
+        .flat_map(|(i, j, weight)| {
+            if i == j {
+                vec![MatrixElement::new(i, j, weight)]
+            } else {
+                vec![
+                    MatrixElement::new(i, j, weight),
+                    MatrixElement::new(j, i, weight),
+                ]
+            }
+        })
+        .collect();
+    new_matrix(n, n, matrix_elements)
+}
+
+/// Parses a Matrix Market coordinate file such as the ones written by
+/// `formats::write_matrix_market` or scipy.io.mmwrite. Integer and pattern matrices
+/// are supported, symmetric files are expanded to both triangles.
+pub fn read_matrix_market<R: Read>(reader: R) -> Result<SparseMatrix<usize>> {
+    let data = read_string(reader)?;
+    let mut lines = data.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    let header = lines
+        .next()
+        .ok_or_else(|| anyhow!("empty Matrix Market file"))?
+        .1
+        .to_lowercase();
+    let header: Vec<&str> = header.split_whitespace().collect();
+    if header.len() != 5 || header[0] != "%%matrixmarket" || header[1] != "matrix" {
+        return Err(anyhow!("line 1: expected a %%MatrixMarket matrix header"));
+    }
+    if header[2] != "coordinate" {
+        return Err(anyhow!("line 1: only the coordinate format is supported"));
+    }
+    let pattern = match header[3] {
+        "integer" => false,
+        "pattern" => true,
+        field => return Err(anyhow!("line 1: unsupported field {}", field)),
+    };
+    let symmetric = match header[4] {
+        "general" => false,
+        "symmetric" => true,
+        symmetry => return Err(anyhow!("line 1: unsupported symmetry {}", symmetry)),
+    };
+
+    let mut lines = lines.filter(|(_, line)| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('%')
+    });
+
+    let (line, size) = lines.next().ok_or_else(|| anyhow!("missing size line"))?;
+    let mut fields = size.split_whitespace();
+    let rows: usize = parse_field(fields.next(), line, "number of rows")?;
+    let columns: usize = parse_field(fields.next(), line, "number of columns")?;
+    let entries: usize = parse_field(fields.next(), line, "number of entries")?;
+
+    let mut matrix_elements = vec![];
+    let mut count = 0;
+    for (line, text) in lines {
+        let mut fields = text.split_whitespace();
+        let i: usize = parse_field(fields.next(), line, "row index")?;
+        let j: usize = parse_field(fields.next(), line, "column index")?;
+        let value: usize = if pattern {
+            1
+        } else {
+            parse_field(fields.next(), line, "value")?
+        };
+
+        if i == 0 || j == 0 || i > rows || j > columns {
+            return Err(anyhow!(
+                "line {}: entry {} {} is outside of the {} x {} matrix",
+                line,
+                i,
+                j,
+                rows,
+                columns
+            ));
+        }
+        matrix_elements.push(MatrixElement::new(i - 1, j - 1, value));
+        if symmetric && i != j {
+            matrix_elements.push(MatrixElement::new(j - 1, i - 1, value));
+        }
+        count += 1;
+    }
+
+    if count != entries {
+        return Err(anyhow!(
+            "size line declares {} entries but the file has {}",
+            entries,
+            count
+        ));
+    }
+
+    new_matrix(rows, columns, matrix_elements)
+}
+
+/// Parses the labels file written by `Relation::write_labels`. Returns the
+/// ingredient names and counts in ID order along with the number of recipes.
+pub fn read_labels<R: Read>(reader: R) -> Result<(Vec<String>, Vec<usize>, usize)> {
+    let data = read_string(reader)?;
+    let mut lines = data.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    let recipe_count = match lines.next() {
+        Some((line, header)) => match header.strip_prefix("# recipes ") {
+            Some(count) => parse(count.trim(), line, "recipe count")?,
+            None => return Err(anyhow!("line {}: expected `# recipes <count>`", line)),
+        },
+        None => return Err(anyhow!("empty labels file")),
+    };
+
+    let mut labels = vec![];
+    let mut counts = vec![];
+    let mut seen = BTreeSet::new();
+    for (line, text) in lines.filter(|(_, text)| !text.trim().is_empty()) {
+        let (label, count) = match text.rfind('\t') {
+            Some(tab) => (&text[..tab], &text[tab + 1..]),
+            None => return Err(anyhow!("line {}: expected `name<TAB>count`", line)),
+        };
+        if label.trim().is_empty() {
+            return Err(anyhow!("line {}: empty ingredient name", line));
+        }
+        if !seen.insert(label) {
+            return Err(anyhow!("line {}: {} appears more than once", line, label));
+        }
+        labels.push(label.to_string());
+        counts.push(parse(count.trim(), line, "count")?);
+    }
+
+    Ok((labels, counts, recipe_count))
+}
+
+/// Rebuilds a `Relation` from a co-occurrence matrix read by `read_csr` or
+/// `read_matrix_market` and its labels. The ingredient counts are taken from the
+/// diagonal, which `recipe_analysis::export` and `formats::write_matrix_market`
+/// keep, so every ingredient must have a diagonal entry. recipe_count is the
+/// number of recipes the relation was built from.
+pub fn relation_from_matrix(
+    labels: Vec<String>,
+    matrix: SparseMatrix<usize>,
+    recipe_count: usize,
+) -> Result<Relation> {
+    if matrix.num_rows() != matrix.num_columns() {
+        return Err(anyhow!(
+            "co-occurrence matrix must be square, found {} x {}",
+            matrix.num_rows(),
+            matrix.num_columns()
+        ));
+    }
+    if matrix.num_rows() != labels.len() {
+        return Err(anyhow!(
+            "{} labels for {} ingredients",
+            labels.len(),
+            matrix.num_rows()
+        ));
+    }
+
+    let mut has_count = vec![false; labels.len()];
+    for (i, _, _) in triplets(&matrix).filter(|(i, j, _)| i == j) {
+        has_count[i] = true;
+    }
+    if let Some(id) = has_count.iter().position(|x| !x) {
+        return Err(anyhow!(
+            "{} has no diagonal entry so its count can't be recovered",
+            labels[id]
+        ));
+    }
+
+    Ok(Relation::from_matrix(labels, matrix, recipe_count))
+}
+
+/// Reads back the ingredient_ingredient.coo and ingredient_labels.txt files
+/// written to a directory by `Relation::write_files`, restoring the diagonal from
+/// the counts in the labels file.
+pub fn read_relation_files<P: AsRef<Path>>(dir: P) -> Result<Relation> {
+    let dir = dir.as_ref();
+
+    let path = dir.join("ingredient_labels.txt");
+    let (labels, counts, recipe_count) = open(&path)
+        .and_then(read_labels)
+        .with_context(|| format!("reading {}", path.display()))?;
+
+    let path = dir.join("ingredient_ingredient.coo");
+    let triplets = open(&path)
+        .and_then(read_triplets)
+        .and_then(|triplets| {
+            size(&triplets, Some(labels.len()))?;
+            match triplets.iter().find(|(i, j, _)| i == j) {
+                Some((i, _, _)) => Err(anyhow!("unexpected diagonal entry for vertex {}", i)),
+                None => Ok(triplets),
+            }
+        })
+        .with_context(|| format!("reading {}", path.display()))?;
+
+    let n = labels.len();
+    let matrix_elements = triplets
+        .into_iter()
+        .chain(counts.iter().enumerate().map(|(i, count)| (i, i, *count)))
+        .map(|(i, j, value)| MatrixElement::new(i, j, value))
+        .collect();
+
+    relation_from_matrix(labels, new_matrix(n, n, matrix_elements)?, recipe_count)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    Ok(BufReader::new(File::open(path)?))
+}
+
+fn read_string<R: Read>(mut reader: R) -> Result<String> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+// Non-empty lines along with their 1-based line numbers
+fn numbered_lines(data: &str) -> impl Iterator<Item = (usize, &str)> {
+    data.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+// `i j value` lines
+fn read_triplets<R: Read>(reader: R) -> Result<Vec<(usize, usize, usize)>> {
+    let data = read_string(reader)?;
+
+    numbered_lines(&data)
+        .map(|(line, text)| {
+            let mut fields = text.split_whitespace();
+            let i = parse_field(fields.next(), line, "row index")?;
+            let j = parse_field(fields.next(), line, "column index")?;
+            let value = parse_field(fields.next(), line, "value")?;
+            if fields.next().is_some() {
+                return Err(anyhow!("line {}: expected `i j value`", line));
+            }
+            Ok((i, j, value))
+        })
+        .collect()
+}
+
+// Number of vertices, checking every index fits when it is given
+fn size(triplets: &[(usize, usize, usize)], n: Option<usize>) -> Result<usize> {
+    let largest = triplets
+        .iter()
+        .map(|(i, j, _)| i.max(j) + 1)
+        .max()
+        .unwrap_or(0);
+    match n {
+        Some(n) if largest > n => Err(anyhow!(
+            "vertex {} is out of bounds for {} vertices",
+            largest - 1,
+            n
+        )),
+        Some(n) => Ok(n),
+        None => Ok(largest),
+    }
+}
+
+fn parse<T: FromStr>(text: &str, line: usize, what: &str) -> Result<T> {
+    text.parse()
+        .map_err(|_| anyhow!("line {}: expected {}, found {:?}", line, what, text))
+}
+
+fn parse_field<T: FromStr>(field: Option<&str>, line: usize, what: &str) -> Result<T> {
+    match field {
+        Some(text) => parse(text, line, what),
+        None => Err(anyhow!("line {}: missing {}", line, what)),
+    }
+}
+
+fn new_matrix(
+    rows: usize,
+    columns: usize,
+    matrix_elements: Vec<MatrixElement<usize>>,
+) -> Result<SparseMatrix<usize>> {
+    SparseMatrix::new(rows, columns, matrix_elements)
+        .map_err(|e| anyhow!("invalid matrix: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::co_occurrence::tests::relation;
+    use crate::formats::write_matrix_market;
+
+    fn entries(matrix: &SparseMatrix<usize>) -> Vec<(usize, usize, usize)> {
+        triplets(matrix).collect()
+    }
+
+    #[tokio::test]
+    async fn write_files_round_trip() {
+        let relation = relation().await;
+        let dir = std::env::temp_dir().join(format!("recipe_analysis_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        relation.write_files(&dir).unwrap();
+        let loaded = read_relation_files(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.get_ingredient_list(), relation.get_ingredient_list());
+        assert_eq!(loaded.get_counts(), relation.get_counts());
+        assert_eq!(loaded.get_recipe_count(), 3);
+        assert_eq!(entries(loaded.get_matrix()), entries(relation.get_matrix()));
+    }
+
+    #[tokio::test]
+    async fn matrix_market_and_csr_round_trip() {
+        let relation = relation().await;
+
+        let mut mm = vec![];
+        write_matrix_market(relation.get_matrix(), &mut mm).unwrap();
+        let matrix = read_matrix_market(&mm[..]).unwrap();
+        assert_eq!(entries(&matrix), entries(relation.get_matrix()));
+
+        let csr = crate::export(relation.get_matrix());
+        let matrix = read_csr(csr.as_bytes()).unwrap();
+        let loaded =
+            relation_from_matrix(relation.get_ingredient_list().clone(), matrix, 3).unwrap();
+        assert_eq!(loaded.get_counts(), relation.get_counts());
+    }
+
+    #[test]
+    fn csr_row_pointers_must_not_decrease() {
+        let error = read_csr("2\n2\n0\n2\n1\n0\n1\n1\n1".as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("never decrease"));
+    }
+
+    #[test]
+    fn matrix_market_entry_count_must_match() {
+        let file = "%%MatrixMarket matrix coordinate integer general\n2 2 3\n1 1 1\n2 2 1\n";
+        let error = read_matrix_market(file.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("declares 3 entries"));
+    }
+
+    #[test]
+    fn expanded_edges_must_be_unique() {
+        let error = read_expanded_coolist("0 1 1\n1 0 2\n".as_bytes(), None).unwrap_err();
+        assert!(error.to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn indices_must_be_in_bounds() {
+        let error = read_coolist("0 1 1\n0 5 1\n".as_bytes(), Some(3)).unwrap_err();
+        assert!(error.to_string().contains("out of bounds"));
+
+        let file = "%%MatrixMarket matrix coordinate integer general\n2 2 1\n3 1 1\n";
+        let error = read_matrix_market(file.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn labels_must_be_unique() {
+        let file = "# recipes 2\nflour\t1\nsugar\t2\nflour\t1\n";
+        let error = read_labels(file.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("line 4"));
+    }
+}
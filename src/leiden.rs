@@ -0,0 +1,176 @@
+use crate::graph::{interpolation_matrix, renumber, Graph};
+use crate::louvain::move_nodes;
+use matrixlab::matrix::sparse::SparseMatrix;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+// Randomness of the refinement phase. Smaller values make the merges closer to
+// greedy, Traag et. al. recommend 0.01 for modularity.
+const THETA: f64 = 0.01;
+
+/// Applies Traag et. al.'s Leiden community detection algorithm to a symmetric
+/// adjacency matrix, such as the one built by
+/// `ExpandedIngredientRelation::build_adjacency_matrix`. Unlike Louvain's every
+/// community is guaranteed to be connected. Returns a Vec of interpolation matrices
+/// in the same form as `louvain::louvain`. The seed makes the randomized
+/// refinement reproducible.
+pub fn leiden(
+    adjacency: &SparseMatrix<usize>,
+    resolution: f64,
+    seed: u64,
+) -> Vec<SparseMatrix<usize>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut graph = Graph::from_matrix(adjacency);
+    let mut membership: Vec<usize> = (0..graph.num_nodes()).collect();
+    let mut interpolation_matrices = vec![];
+
+    loop {
+        move_nodes(&graph, &mut membership, resolution);
+        let num_communities = renumber(&mut membership);
+
+        if num_communities == graph.num_nodes() {
+            break;
+        }
+
+        let mut refined = refine(&graph, &membership, resolution, &mut rng);
+        let mut num_refined = renumber(&mut refined);
+
+        // Refinement did not merge anything so aggregate on the unrefined partition
+        // instead, otherwise the graph would never shrink.
+        if num_refined == graph.num_nodes() {
+            refined = membership.clone();
+            num_refined = num_communities;
+        }
+
+        // The aggregate graph starts from the unrefined partition
+        let mut next = vec![0; num_refined];
+        for (node, &community) in refined.iter().enumerate() {
+            next[community] = membership[node];
+        }
+
+        interpolation_matrices.push(interpolation_matrix(&refined, num_refined));
+        graph = graph.aggregate(&refined, num_refined);
+        membership = next;
+    }
+
+    // always report at least one level, even if it is all singletons
+    if interpolation_matrices.is_empty() {
+        interpolation_matrices.push(interpolation_matrix(&membership, membership.len()));
+    }
+
+    interpolation_matrices
+}
+
+// Refinement phase of Leiden. Every community is split back into singletons which
+// are then merged, in random order, with well connected subcommunities of the same
+// community. A vertex only merges while it is still a singleton.
+fn refine(graph: &Graph, membership: &[usize], resolution: f64, rng: &mut StdRng) -> Vec<usize> {
+    let n = graph.num_nodes();
+    let total_weight = graph.total_weight();
+    let mut refined: Vec<usize> = (0..n).collect();
+
+    // degree totals of the unrefined communities
+    let mut community_totals = vec![0.0; n];
+    for node in 0..n {
+        community_totals[membership[node]] += graph.degree(node);
+    }
+
+    // weight from each vertex to the rest of its community
+    let node_internal: Vec<f64> = (0..n)
+        .map(|node| {
+            graph
+                .neighbors(node)
+                .iter()
+                .filter(|(neighbor, _)| {
+                    *neighbor != node && membership[*neighbor] == membership[node]
+                })
+                .map(|(_, weight)| weight)
+                .sum()
+        })
+        .collect();
+
+    let mut refined_totals: Vec<f64> = (0..n).map(|node| graph.degree(node)).collect();
+    let mut refined_external = node_internal.clone();
+    let mut refined_sizes = vec![1; n];
+
+    let mut weights = vec![0.0; n];
+    let mut touched = vec![];
+    let mut candidates: Vec<(usize, f64)> = vec![];
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.shuffle(rng);
+
+    for node in order {
+        let own = refined[node];
+        let community = membership[node];
+        let degree = graph.degree(node);
+
+        if refined_sizes[own] != 1 {
+            continue;
+        }
+
+        let remainder = community_totals[community] - degree;
+        if node_internal[node] < resolution * degree * remainder / total_weight {
+            continue;
+        }
+
+        for &(neighbor, weight) in graph.neighbors(node) {
+            if neighbor == node || membership[neighbor] != community {
+                continue;
+            }
+            let target = refined[neighbor];
+            if weights[target] == 0.0 {
+                touched.push(target);
+            }
+            weights[target] += weight;
+        }
+
+        candidates.push((own, 0.0));
+        for &target in touched.iter() {
+            let total = refined_totals[target];
+            let remainder = community_totals[community] - total;
+            let well_connected =
+                refined_external[target] >= resolution * total * remainder / total_weight;
+            let gain = weights[target] - resolution * total * degree / total_weight;
+
+            if well_connected && gain >= 0.0 {
+                candidates.push((target, gain / total_weight));
+            }
+        }
+
+        let max_gain = candidates
+            .iter()
+            .map(|(_, gain)| *gain)
+            .fold(f64::MIN, f64::max);
+        let probabilities: Vec<f64> = candidates
+            .iter()
+            .map(|(_, gain)| ((gain - max_gain) / THETA).exp())
+            .collect();
+        let mut choice = rng.gen::<f64>() * probabilities.iter().sum::<f64>();
+        let mut chosen = own;
+        for ((target, _), probability) in candidates.iter().zip(probabilities.iter()) {
+            chosen = *target;
+            if choice < *probability {
+                break;
+            }
+            choice -= probability;
+        }
+
+        if chosen != own {
+            refined_totals[chosen] += degree;
+            refined_external[chosen] += node_internal[node] - 2.0 * weights[chosen];
+            refined_sizes[chosen] += 1;
+            refined_sizes[own] = 0;
+            refined[node] = chosen;
+        }
+
+        for &target in touched.iter() {
+            weights[target] = 0.0;
+        }
+        touched.clear();
+        candidates.clear();
+    }
+
+    refined
+}
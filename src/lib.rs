@@ -1,21 +1,64 @@
+/// Sparsification of the ingredient network down to its significant edges
+pub mod backbone;
 #[macro_use]
 /// Simple ingredient co-occurrence relationship to investigate if structure exists in
 /// the network of cooking ingredients.
 pub mod co_occurrence;
+/// Connection settings for the recipe databases
+pub mod config;
+/// Ingredient coordinates learned from the co-occurrence network, replacing the
+/// external graph-embed binary
+pub mod embedding;
 /// Expanded relationship to allow ingredients to participate in multiple communities
 pub mod expanded;
+/// GraphML, GEXF, DOT, Matrix Market and CSV exports of the ingredient network
+pub mod formats;
+/// Weighted graph representation shared by the partitioning algorithms
+mod graph;
 /// Hierarchy of partitions created through one of the modularity based graph partitioning
 /// algorithms
 pub mod hierarchy;
-/// Wrapper module to run Blondel et. al.'s implementation of Louvain modulatiry partitioning
+/// Readers for the graph files written by `export` and `formats`, so expensive
+/// queries can be cached
+pub mod import;
+/// Traag et. al.'s Leiden partitioning, a refinement of Louvain's that guarantees
+/// connected communities
+pub mod leiden;
+/// Native implementation of Blondel et. al.'s Louvain modularity partitioning
 pub mod louvain;
+/// Canonicalization of raw ingredient names before they are assigned IDs
+pub mod normalize;
+/// Recommendations of ingredients to add to a partial recipe
+pub mod pairing;
+/// Common interface over the partitioning algorithms so they can be compared on the
+/// same graph
+pub mod partition;
+/// Reusable database connections for issuing many queries
+pub mod pool;
 pub mod recipe;
+/// Association rule mining over the ingredient sets of recipes
+pub mod rules;
+/// Recipe x recipe similarity and nearest recipe search
+pub mod similarity;
+/// Versioned JSON and binary snapshots of relations and hierarchies
+pub mod snapshot;
+/// Interchangeable sources of recipes, either the database or local files
+pub mod source;
+/// Recursive spectral bisection partitioning
+pub mod spectral;
+/// Ingredient substitution suggestions from shared co-occurrence contexts
+pub mod substitution;
+/// Roll up of ingredients into categories such as dairy, herb and protein
+pub mod taxonomy;
 
 //pub mod graph_explorer;
 
 use anyhow::Result;
+use config::{DbConfig, TlsMode};
 use matrixlab::matrix::sparse::SparseMatrix;
-use tokio_postgres::{Client, Config, NoTls};
+use std::sync::{Arc, Mutex};
+use tokio_postgres::tls::MakeTlsConnect;
+use tokio_postgres::{Client, Config, NoTls, Socket};
 
 #[derive(Clone, Copy)]
 pub enum Databases {
@@ -32,21 +75,77 @@ impl Databases {
     }
 }
 
+/// Connects to one of the recipe databases using the settings from
+/// `DbConfig::load`
 pub async fn connect_db(db: Databases) -> Result<Client> {
-    let (client, connection) = Config::new()
-        .user("austen")
-        .dbname(db.to_str())
-        .host("localhost")
-        .connect(NoTls)
-        .await?;
+    connect_db_with(&DbConfig::load()?, db).await
+}
+
+/// Connects to one of the recipe databases. An error that closes the connection
+/// later is only reported as "connection closed" by the next query, use
+/// `pool::Pool` to keep the error that caused it.
+pub async fn connect_db_with(config: &DbConfig, db: Databases) -> Result<Client> {
+    let (client, _) = open_db(config, db).await?;
+    Ok(client)
+}
 
+// Connects along with the slot the connection's error is stored in when it fails
+pub(crate) async fn open_db(config: &DbConfig, db: Databases) -> Result<(Client, ConnectionError)> {
+    let pg_config = config.to_pg_config(db)?;
+    // a URL's sslmode takes precedence over config.tls
+    let tls = TlsMode::from_ssl_mode(pg_config.get_ssl_mode());
+
+    #[cfg(feature = "tls")]
+    let connection = if tls == TlsMode::Disable {
+        connect(&pg_config, NoTls).await?
+    } else {
+        let connector = native_tls::TlsConnector::new()?;
+        connect(
+            &pg_config,
+            postgres_native_tls::MakeTlsConnector::new(connector),
+        )
+        .await?
+    };
+
+    #[cfg(not(feature = "tls"))]
+    let connection = if tls == TlsMode::Require {
+        return Err(anyhow::anyhow!(
+            "TLS is required but recipe_analysis was built without the tls feature"
+        ));
+    } else {
+        connect(&pg_config, NoTls).await?
+    };
+
+    Ok(connection)
+}
+
+/// The error that closed a connection, kept by the task driving the connection
+/// since queries made with the client only see "connection closed"
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionError(Arc<Mutex<Option<tokio_postgres::Error>>>);
+
+impl ConnectionError {
+    pub(crate) fn message(&self) -> Option<String> {
+        self.0.lock().unwrap().as_ref().map(|e| e.to_string())
+    }
+}
+
+async fn connect<T>(pg_config: &Config, tls: T) -> Result<(Client, ConnectionError)>
+where
+    T: MakeTlsConnect<Socket>,
+    T::Stream: Send + 'static,
+{
+    let (client, connection) = pg_config.connect(tls).await?;
+
+    let error = ConnectionError::default();
+    let slot = error.clone();
     tokio::spawn(async move {
         if let Err(e) = connection.await {
-            panic!("connection error: {}", e);
+            *slot.0.lock().unwrap() = Some(e);
         }
     });
 
-    Ok(client)
+    Ok((client, error))
 }
 
 // exports the matrix into the format that graph-embed binary can intake
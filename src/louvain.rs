@@ -1,61 +1,147 @@
+use crate::graph::{interpolation_matrix, renumber, Graph};
+use anyhow::{anyhow, Result};
 use matrixlab::matrix::sparse::SparseMatrix;
 use matrixlab::MatrixElement;
-use std::fs::File;
-use std::io::prelude::*;
-use tokio::process::Command;
-
-/// Applies louvains community detection algorithm to the expanded graph saved at
-/// temp/expanded_coolist. Returns a Vec of interpolation matrices. Taking the
-/// original adjacency matrix, A, and the interpolation matrix, P_0, then
-/// A_coarse1 = P_0^t * A * P_0
-pub async fn louvain(n: usize) -> Vec<SparseMatrix<usize>> {
-    let _result = Command::new("../louvain/convert")
-        .args(&[
-            "-i",
-            "temp/expanded_coolist",
-            "-o",
-            "temp/graph.bin",
-            "-w",
-            "temp/graph.weights",
-        ])
-        .output()
-        .await
-        .unwrap();
-
-    let tree = Command::new("../louvain/community")
-        .args(&["temp/graph.bin", "-l", "-1", "-w", "temp/graph.weights"])
-        .output()
-        .await
-        .unwrap();
-
-    let mut louvain = File::create("temp/louvain_hierarchy").unwrap();
-    louvain.write_all(&tree.stdout).unwrap();
-
-    build_interpolation_matrices(n)
+use std::io::Read;
+
+// Smallest modularity gain that counts as an improvement. Keeps floating point
+// noise from moving vertices back and forth forever.
+const MIN_GAIN: f64 = 1e-12;
+
+/// Applies Louvain's community detection algorithm to a symmetric adjacency matrix,
+/// such as the one built by `ExpandedIngredientRelation::build_adjacency_matrix`.
+/// Returns a Vec of interpolation matrices. Taking the original adjacency matrix, A,
+/// and the interpolation matrix, P_0, then A_coarse1 = P_0^t * A * P_0
+pub fn louvain(adjacency: &SparseMatrix<usize>) -> Vec<SparseMatrix<usize>> {
+    let mut graph = Graph::from_matrix(adjacency);
+    let mut interpolation_matrices = vec![];
+
+    loop {
+        let mut membership: Vec<usize> = (0..graph.num_nodes()).collect();
+        let improved = move_nodes(&graph, &mut membership, 1.0);
+        let num_communities = renumber(&mut membership);
+
+        if !improved || num_communities == graph.num_nodes() {
+            // always report at least one level, even if it is all singletons
+            if interpolation_matrices.is_empty() {
+                interpolation_matrices.push(interpolation_matrix(&membership, num_communities));
+            }
+            break;
+        }
+
+        interpolation_matrices.push(interpolation_matrix(&membership, num_communities));
+        graph = graph.aggregate(&membership, num_communities);
+    }
+
+    interpolation_matrices
+}
+
+// First phase of Louvain's. Repeatedly moves single vertices into the neighboring
+// community with the largest modularity gain until no vertex moves. Returns true
+// if any vertex changed communities.
+pub(crate) fn move_nodes(graph: &Graph, membership: &mut [usize], resolution: f64) -> bool {
+    let total_weight = graph.total_weight();
+    if total_weight == 0.0 {
+        return false;
+    }
+
+    let n = graph.num_nodes();
+    let mut totals = vec![0.0; n];
+    for node in 0..n {
+        totals[membership[node]] += graph.degree(node);
+    }
+
+    // weight from the current vertex to each neighboring community
+    let mut weights = vec![0.0; n];
+    let mut touched = vec![];
+    let mut improved = false;
+
+    loop {
+        let mut moved = false;
+
+        for node in 0..n {
+            let current = membership[node];
+            let degree = graph.degree(node);
+
+            for &(neighbor, weight) in graph.neighbors(node) {
+                if neighbor == node {
+                    continue;
+                }
+                let community = membership[neighbor];
+                if weights[community] == 0.0 {
+                    touched.push(community);
+                }
+                weights[community] += weight;
+            }
+
+            totals[current] -= degree;
+            let mut best = current;
+            let mut best_gain =
+                weights[current] - resolution * totals[current] * degree / total_weight;
+
+            for &community in touched.iter() {
+                let gain =
+                    weights[community] - resolution * totals[community] * degree / total_weight;
+                if gain > best_gain + MIN_GAIN {
+                    best = community;
+                    best_gain = gain;
+                }
+            }
+
+            totals[best] += degree;
+            membership[node] = best;
+            if best != current {
+                moved = true;
+                improved = true;
+            }
+
+            for &community in touched.iter() {
+                weights[community] = 0.0;
+            }
+            touched.clear();
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    improved
 }
 
-// Helper method that constructs the interpolation matrices after Louvain's
-// has created the partition tree.
-fn build_interpolation_matrices(n: usize) -> Vec<SparseMatrix<usize>> {
+/// Constructs the interpolation matrices from a partition tree written by Blondel
+/// et. al.'s community binary, such as the temp/louvain_hierarchy files it used to
+/// produce. n is the number of vertices in the original graph. Useful for reusing
+/// partitions computed before the native implementation existed.
+pub fn build_interpolation_matrices<R: Read>(
+    n: usize,
+    mut reader: R,
+) -> Result<Vec<SparseMatrix<usize>>> {
     // number of vertices at each level
     let mut start = 0;
     let mut end = n;
-    let data = std::fs::read_to_string("temp/louvain_hierarchy").unwrap();
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
 
     let tree: Vec<(usize, usize)> = data
         .trim()
-        .split('\n')
-        .map(|line| {
-            let mut vertex = line.split_ascii_whitespace();
-            let node = vertex.next().unwrap().parse().unwrap();
-            let group = vertex.next().unwrap().parse().unwrap();
-            (node, group)
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let mut vertex = line.split_ascii_whitespace().map(|x| x.parse::<usize>());
+            match (vertex.next(), vertex.next()) {
+                (Some(Ok(node)), Some(Ok(group))) => Ok((node, group)),
+                _ => Err(anyhow!("line {}: expected `node group`", i + 1)),
+            }
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     let mut interpolation_matrices = vec![];
 
     while end <= tree.len() + 1 {
+        if end > tree.len() {
+            return Err(anyhow!("partition tree is shorter than {} vertices", end));
+        }
         let mut matrix_elements: Vec<MatrixElement<usize>> = vec![];
         let mut num_aggs = 0;
         let n = end - start;
@@ -67,14 +153,13 @@ fn build_interpolation_matrices(n: usize) -> Vec<SparseMatrix<usize>> {
             matrix_elements.push(MatrixElement::new(*node, *group, 1));
         }
 
-        println!("num aggs: {}", num_aggs);
-        let interpolation_matrix = SparseMatrix::new(n, num_aggs + 1, matrix_elements).unwrap();
-        println!("level");
+        let interpolation_matrix = SparseMatrix::new(n, num_aggs + 1, matrix_elements)
+            .map_err(|e| anyhow!("invalid partition tree: {:?}", e))?;
         interpolation_matrices.push(interpolation_matrix);
 
         start = end;
         end += num_aggs;
     }
 
-    interpolation_matrices
+    Ok(interpolation_matrices)
 }
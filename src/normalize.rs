@@ -0,0 +1,345 @@
+use crate::source::split_csv_line;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+// Quantities, units and preparation words that don't change what the ingredient is
+const STOP_WORDS: &[&str] = &[
+    "a",
+    "about",
+    "and",
+    "as",
+    "can",
+    "cans",
+    "chopped",
+    "coarsely",
+    "cold",
+    "crushed",
+    "cube",
+    "cubed",
+    "cubes",
+    "cup",
+    "cups",
+    "dash",
+    "diced",
+    "divided",
+    "drained",
+    "extra",
+    "finely",
+    "for",
+    "fresh",
+    "freshly",
+    "g",
+    "gram",
+    "grams",
+    "grated",
+    "halved",
+    "handful",
+    "kg",
+    "large",
+    "lb",
+    "lbs",
+    "medium",
+    "melted",
+    "minced",
+    "ml",
+    "needed",
+    "of",
+    "optional",
+    "or",
+    "ounce",
+    "ounces",
+    "oz",
+    "package",
+    "packages",
+    "peeled",
+    "pinch",
+    "pound",
+    "pounds",
+    "quartered",
+    "rinsed",
+    "room",
+    "serving",
+    "shredded",
+    "sliced",
+    "small",
+    "softened",
+    "taste",
+    "tablespoon",
+    "tablespoons",
+    "tbsp",
+    "teaspoon",
+    "teaspoons",
+    "temperature",
+    "thinly",
+    "to",
+    "tsp",
+    "warm",
+    "whole",
+];
+
+// Words ending in s that are already singular
+const SINGULAR: &[&str] = &[
+    "asparagus",
+    "brussels",
+    "citrus",
+    "couscous",
+    "hummus",
+    "molasses",
+    "swiss",
+    "octopus",
+    "lemongrass",
+    "bass",
+    "watercress",
+];
+
+// Plurals the suffix rules get wrong
+const IRREGULAR: &[(&str, &str)] = &[
+    ("leaves", "leaf"),
+    ("halves", "half"),
+    ("loaves", "loaf"),
+    ("knives", "knife"),
+    ("anchovies", "anchovy"),
+    ("sandwiches", "sandwich"),
+    // plurals of words ending in -ie rather than -y
+    ("pies", "pie"),
+    ("cookies", "cookie"),
+    ("brownies", "brownie"),
+    ("smoothies", "smoothie"),
+    ("veggies", "veggie"),
+    ("goodies", "goodie"),
+    ("hoagies", "hoagie"),
+];
+
+/// Maps raw ingredient names from the database onto canonical names so that
+/// variants such as "Tomatoes", "tomato" and "2 chopped tomatoes" become the same
+/// vertex. Names are lowercased, parenthesized text, punctuation, numbers and
+/// quantity/preparation words are removed, and the last word is made singular.
+/// Synonyms are applied to both the raw and the cleaned name.
+pub struct Normalizer {
+    synonyms: BTreeMap<String, String>,
+    stop_words: BTreeSet<String>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self {
+            synonyms: BTreeMap::new(),
+            stop_words: STOP_WORDS.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names, raw or cleaned, that should become `canonical`. For example
+    /// "roma tomato" -> "tomato".
+    pub fn add_synonym<S: Into<String>>(&mut self, name: S, canonical: S) {
+        self.synonyms
+            .insert(name.into().to_lowercase(), canonical.into().to_lowercase());
+    }
+
+    /// Extra words that are removed from every name
+    pub fn add_stop_word<S: Into<String>>(&mut self, word: S) {
+        self.stop_words.insert(word.into().to_lowercase());
+    }
+
+    /// Reads a synonym table with one `name,canonical` pair per line
+    pub fn load_synonyms<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let data = std::fs::read_to_string(path)?;
+
+        for (i, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = split_csv_line(line).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+            if fields.len() != 2 {
+                return Err(anyhow!("line {}: expected name,canonical", i + 1));
+            }
+            self.add_synonym(fields[0].trim(), fields[1].trim());
+        }
+
+        Ok(())
+    }
+
+    /// Canonical form of a single ingredient name
+    pub fn normalize(&self, raw: &str) -> String {
+        let lower = raw.trim().to_lowercase();
+        if let Some(canonical) = self.synonyms.get(&lower) {
+            return canonical.clone();
+        }
+
+        // drop parenthesized notes like "(about 2 cups)"
+        let mut depth = 0;
+        let mut text = String::with_capacity(lower.len());
+        for c in lower.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                c if depth == 0 => {
+                    if c.is_alphanumeric() || c == '-' {
+                        text.push(c);
+                    } else {
+                        text.push(' ');
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let mut words: Vec<String> = text
+            .split_whitespace()
+            .filter(|word| !word.chars().any(|c| c.is_ascii_digit()))
+            .filter(|word| !self.stop_words.contains(*word))
+            .map(String::from)
+            .collect();
+
+        if let Some(last) = words.last_mut() {
+            *last = singular(last);
+        }
+
+        let cleaned = words.join(" ");
+        if cleaned.is_empty() {
+            return lower;
+        }
+        self.synonyms.get(&cleaned).cloned().unwrap_or(cleaned)
+    }
+
+    /// Normalizes the ingredients of every recipe, merging ingredients within a
+    /// recipe that end up with the same name. Returns the recipes ready for
+    /// `co_occurrence::make_relation` along with a report of the merges.
+    pub fn normalize_recipes(
+        &self,
+        recipes: &[(i32, Vec<String>)],
+    ) -> (Vec<(i32, Vec<String>)>, NormalizationReport) {
+        let mut report = NormalizationReport {
+            merged: BTreeMap::new(),
+        };
+
+        let recipes = recipes
+            .iter()
+            .map(|(id, ingredients)| {
+                let mut names = vec![];
+                for raw in ingredients {
+                    let name = self.normalize(raw);
+                    report
+                        .merged
+                        .entry(name.clone())
+                        .or_default()
+                        .insert(raw.clone());
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                (*id, names)
+            })
+            .collect();
+
+        (recipes, report)
+    }
+}
+
+fn singular(word: &str) -> String {
+    if let Some((_, singular)) = IRREGULAR.iter().find(|(plural, _)| *plural == word) {
+        return singular.to_string();
+    }
+    if word.len() <= 3 || SINGULAR.contains(&word) {
+        return word.to_string();
+    }
+
+    if let Some(stem) = word.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if let Some(stem) = word.strip_suffix("es").filter(|stem| adds_es(stem)) {
+        stem.to_string()
+    } else if word.ends_with("ss") || word.ends_with("us") || word.ends_with("is") {
+        word.to_string()
+    } else if let Some(stem) = word.strip_suffix('s') {
+        stem.to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+// Whether a plural ending in -es was formed by adding "es" to the stem rather
+// than "s" to a word that already ends in e
+fn adds_es(stem: &str) -> bool {
+    let consonant = |c: char| !"aeiou".contains(c);
+    if let Some(rest) = stem.strip_suffix("ch") {
+        // bunches and peaches but not quiches, brioches or ganaches
+        rest.ends_with(consonant) || rest.ends_with("ea") || rest.ends_with("oa")
+    } else if let Some(rest) = stem.strip_suffix('o') {
+        // tomatoes and mangoes but not sloes, aloes or shoes
+        rest.len() > 2 && rest.ends_with(consonant)
+    } else {
+        ["sh", "ss", "x", "z"]
+            .iter()
+            .any(|suffix| stem.ends_with(suffix))
+    }
+}
+
+/// Which raw names were mapped onto each canonical name
+pub struct NormalizationReport {
+    // Key: canonical name Value: raw names
+    merged: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl NormalizationReport {
+    pub fn get_mapping(&self) -> &BTreeMap<String, BTreeSet<String>> {
+        &self.merged
+    }
+
+    /// Only the canonical names that differ from their raw name or that more than
+    /// one raw name was merged into
+    pub fn merges(&self) -> impl Iterator<Item = (&String, &BTreeSet<String>)> {
+        self.merged
+            .iter()
+            .filter(|(name, raw)| raw.len() > 1 || !raw.contains(*name))
+    }
+}
+
+impl fmt::Display for NormalizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, raw) in self.merges() {
+            let raw: Vec<&str> = raw.iter().map(|x| x.as_str()).collect();
+            writeln!(f, "{} <- {}", name, raw.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plurals_become_singular() {
+        let normalizer = Normalizer::new();
+        assert_eq!(normalizer.normalize("2 chopped Tomatoes"), "tomato");
+        assert_eq!(normalizer.normalize("peaches"), "peach");
+        assert_eq!(normalizer.normalize("fresh cherries"), "cherry");
+        assert_eq!(normalizer.normalize("bay leaves"), "bay leaf");
+        assert_eq!(normalizer.normalize("asparagus"), "asparagus");
+        assert_eq!(normalizer.normalize("mangoes"), "mango");
+        assert_eq!(normalizer.normalize("radishes"), "radish");
+        assert_eq!(normalizer.normalize("bunches"), "bunch");
+        assert_eq!(normalizer.normalize("quiches"), "quiche");
+        assert_eq!(normalizer.normalize("brioches"), "brioche");
+        assert_eq!(normalizer.normalize("ganaches"), "ganache");
+        assert_eq!(normalizer.normalize("sloes"), "sloe");
+        assert_eq!(normalizer.normalize("sandwiches"), "sandwich");
+    }
+
+    #[test]
+    fn ie_plurals_keep_their_ending() {
+        let normalizer = Normalizer::new();
+        assert_eq!(normalizer.normalize("Pies"), "pie");
+        assert_eq!(normalizer.normalize("cookies"), "cookie");
+        assert_eq!(normalizer.normalize("Brownies"), "brownie");
+        assert_eq!(normalizer.normalize("smoothies"), "smoothie");
+        assert_eq!(normalizer.normalize("veggies"), "veggie");
+    }
+}
@@ -0,0 +1,143 @@
+use crate::co_occurrence::{Association, Relation};
+use crate::graph::triplets;
+use crate::hierarchy::Hierarchy;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How the co-occurrence rows of the chosen ingredients are combined into a score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// Sum of the number of recipes the candidate shares with each chosen ingredient
+    Sum,
+    /// Sum of the pointwise mutual information of the candidate with each chosen
+    /// ingredient, which favors specific pairings over ubiquitous ingredients
+    Pmi,
+}
+
+/// An ingredient to add to a partial recipe
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub ingredient: String,
+    pub score: f64,
+    /// Contribution of each chosen ingredient to the score before the community
+    /// boost, largest first
+    pub contributions: Vec<(String, f64)>,
+    /// 1 + boost * (fraction of chosen ingredients sharing a community with the
+    /// candidate), 1 when no hierarchy is used. The score is raised by
+    /// (community_boost - 1) times the magnitude of the summed contributions.
+    pub community_boost: f64,
+}
+
+/// Recommends the next ingredients to add given the ones already chosen
+pub struct PairingRecommender<'a> {
+    relation: &'a Relation,
+    aggregation: Aggregation,
+    // Ingredient names in each community and the strength of the boost
+    communities: Option<(Vec<BTreeSet<String>>, f64)>,
+}
+
+impl<'a> PairingRecommender<'a> {
+    pub fn new(relation: &'a Relation, aggregation: Aggregation) -> Self {
+        Self {
+            relation,
+            aggregation,
+            communities: None,
+        }
+    }
+
+    /// Boosts candidates that share communities with the chosen ingredients at a
+    /// level of the hierarchy. boost * (fraction of chosen ingredients it shares a
+    /// community with) * |score| is added to a candidate's score, so the boost
+    /// always moves it up even when PMI makes the score negative.
+    pub fn with_communities(
+        mut self,
+        hierarchy: &Hierarchy,
+        level: usize,
+        boost: f64,
+    ) -> Result<Self> {
+        self.communities = Some((hierarchy.ingredient_communities(level)?, boost));
+        Ok(self)
+    }
+
+    /// The k best ingredients to add to the chosen ones, best first
+    pub fn recommend(&self, chosen: &[String], k: usize) -> Result<Vec<Suggestion>> {
+        let map = self.relation.get_ingredient_map();
+        let names = self.relation.get_ingredient_list();
+        let counts = self.relation.get_counts();
+        let recipes = self.relation.get_recipe_count() as f64;
+
+        let chosen_ids: BTreeSet<usize> = chosen
+            .iter()
+            .map(|name| {
+                map.get(name)
+                    .map(|(id, _)| *id)
+                    .ok_or_else(|| anyhow!("unknown ingredient: {}", name))
+            })
+            .collect::<Result<_>>()?;
+
+        // Key: candidate ID Value: contribution of each chosen ingredient
+        let mut contributions: BTreeMap<usize, Vec<(usize, f64)>> = BTreeMap::new();
+        for (i, j, count) in triplets(self.relation.get_matrix()) {
+            if i == j || !chosen_ids.contains(&i) || chosen_ids.contains(&j) {
+                continue;
+            }
+            let value = match self.aggregation {
+                Aggregation::Sum => count as f64,
+                Aggregation::Pmi => Association::Pmi.score(
+                    count as f64,
+                    counts[i] as f64,
+                    counts[j] as f64,
+                    recipes,
+                ),
+            };
+            contributions.entry(j).or_default().push((i, value));
+        }
+
+        let mut suggestions: Vec<Suggestion> = contributions
+            .into_iter()
+            .map(|(candidate, mut parts)| {
+                let community_boost = self.community_boost(&names[candidate], chosen);
+                let sum = parts.iter().map(|(_, value)| value).sum::<f64>();
+                let score = sum + (community_boost - 1.0) * sum.abs();
+                parts.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+                Suggestion {
+                    ingredient: names[candidate].clone(),
+                    score,
+                    contributions: parts
+                        .into_iter()
+                        .map(|(i, value)| (names[i].clone(), value))
+                        .collect(),
+                    community_boost,
+                }
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap()
+                .then_with(|| a.ingredient.cmp(&b.ingredient))
+        });
+        suggestions.truncate(k);
+
+        Ok(suggestions)
+    }
+
+    fn community_boost(&self, candidate: &str, chosen: &[String]) -> f64 {
+        match &self.communities {
+            None => 1.0,
+            Some((communities, boost)) => {
+                let shared = chosen
+                    .iter()
+                    .filter(|name| {
+                        communities
+                            .iter()
+                            .any(|members| members.contains(candidate) && members.contains(*name))
+                    })
+                    .count();
+                1.0 + boost * shared as f64 / chosen.len() as f64
+            }
+        }
+    }
+}
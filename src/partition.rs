@@ -0,0 +1,178 @@
+use crate::graph::{membership, Graph};
+use crate::{leiden, louvain, spectral};
+use anyhow::{anyhow, Result};
+use matrixlab::matrix::sparse::SparseMatrix;
+
+/// Hierarchy of partitions produced by a `Partitioner`
+pub struct Partition {
+    interpolation_matrices: Vec<SparseMatrix<usize>>,
+    modularity: Vec<f64>,
+}
+
+impl Partition {
+    /// Wraps a Vec of interpolation matrices, computing the modularity of each level
+    /// against the original adjacency matrix.
+    pub fn new(
+        adjacency: &SparseMatrix<usize>,
+        interpolation_matrices: Vec<SparseMatrix<usize>>,
+    ) -> Partition {
+        let modularity = level_modularity(adjacency, &interpolation_matrices);
+        Partition {
+            interpolation_matrices,
+            modularity,
+        }
+    }
+
+    pub fn get_interpolation_matrices(&self) -> &Vec<SparseMatrix<usize>> {
+        &self.interpolation_matrices
+    }
+
+    /// Modularity of the partition at each level, indexed the same as the
+    /// interpolation matrices
+    pub fn get_modularity(&self) -> &Vec<f64> {
+        &self.modularity
+    }
+
+    /// Community of every vertex of the original graph at a level of the
+    /// hierarchy, 0 being the finest
+    pub fn communities(&self, level: usize) -> Result<Vec<usize>> {
+        if level >= self.interpolation_matrices.len() {
+            return Err(anyhow!("out of range"));
+        }
+        let mut communities = membership(&self.interpolation_matrices[0]);
+        for matrix in self.interpolation_matrices[1..=level].iter() {
+            let coarse = membership(matrix);
+            communities.iter_mut().for_each(|x| *x = coarse[*x]);
+        }
+        Ok(communities)
+    }
+
+    pub fn into_parts(self) -> (Vec<SparseMatrix<usize>>, Vec<f64>) {
+        (self.interpolation_matrices, self.modularity)
+    }
+}
+
+/// A graph partitioning strategy that can be used to build a `Hierarchy`
+pub trait Partitioner {
+    /// Partitions the graph given by a symmetric adjacency matrix, such as the one
+    /// built by `ExpandedIngredientRelation::build_adjacency_matrix`
+    fn partition(&self, adjacency: &SparseMatrix<usize>) -> Partition;
+}
+
+/// Blondel et. al.'s Louvain modularity partitioning
+pub struct Louvain;
+
+impl Partitioner for Louvain {
+    fn partition(&self, adjacency: &SparseMatrix<usize>) -> Partition {
+        Partition::new(adjacency, louvain::louvain(adjacency))
+    }
+}
+
+/// Traag et. al.'s Leiden modularity partitioning
+pub struct Leiden {
+    pub resolution: f64,
+    pub seed: u64,
+}
+
+impl Default for Leiden {
+    fn default() -> Self {
+        Self {
+            resolution: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Partitioner for Leiden {
+    fn partition(&self, adjacency: &SparseMatrix<usize>) -> Partition {
+        Partition::new(
+            adjacency,
+            leiden::leiden(adjacency, self.resolution, self.seed),
+        )
+    }
+}
+
+/// Recursive spectral bisection
+pub struct Spectral {
+    pub levels: usize,
+    pub iterations: usize,
+    pub seed: u64,
+}
+
+impl Default for Spectral {
+    fn default() -> Self {
+        Self {
+            levels: 4,
+            iterations: 200,
+            seed: 0,
+        }
+    }
+}
+
+impl Partitioner for Spectral {
+    fn partition(&self, adjacency: &SparseMatrix<usize>) -> Partition {
+        Partition::new(
+            adjacency,
+            spectral::spectral(adjacency, self.levels, self.iterations, self.seed),
+        )
+    }
+}
+
+/// Modularity of the partition at every level of a hierarchy. Modularity is
+/// unchanged by aggregation so each level is measured on the coarse graph
+/// A_coarse = P^t * A * P of the level below it.
+pub fn level_modularity(
+    adjacency: &SparseMatrix<usize>,
+    interpolation_matrices: &[SparseMatrix<usize>],
+) -> Vec<f64> {
+    let mut graph = Graph::from_matrix(adjacency);
+
+    interpolation_matrices
+        .iter()
+        .map(|matrix| {
+            let membership = membership(matrix);
+            let modularity = graph.modularity(&membership, 1.0);
+            graph = graph.aggregate(&membership, matrix.num_columns());
+            modularity
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::two_cliques;
+
+    #[test]
+    fn every_partitioner_splits_two_cliques() {
+        let adjacency = two_cliques();
+        let partitioners: Vec<(&str, Box<dyn Partitioner>)> = vec![
+            ("louvain", Box::new(Louvain)),
+            ("leiden", Box::new(Leiden::default())),
+            ("spectral", Box::new(Spectral::default())),
+        ];
+
+        for (name, partitioner) in partitioners {
+            let partition = partitioner.partition(&adjacency);
+            let top = partition.get_interpolation_matrices().len() - 1;
+            let communities = partition.communities(top).unwrap();
+
+            assert!(
+                communities[..5].iter().all(|x| *x == communities[0]),
+                "{}",
+                name
+            );
+            assert!(
+                communities[5..].iter().all(|x| *x == communities[5]),
+                "{}",
+                name
+            );
+            assert_ne!(communities[0], communities[5], "{}", name);
+            assert!(
+                (partition.get_modularity()[top] - 0.452).abs() < 1e-3,
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::config::DbConfig;
+use crate::{open_db, ConnectionError, Databases};
+use anyhow::{anyhow, Result};
+use std::ops::Deref;
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio_postgres::Client;
+
+/// A fixed size pool of connections to one of the recipe databases. Connections
+/// are opened lazily and reused by later queries, closed connections are dropped
+/// and replaced on the next `Pool::get`.
+pub struct Pool {
+    config: DbConfig,
+    db: Databases,
+    idle: Mutex<Vec<(Client, ConnectionError)>>,
+    permits: Semaphore,
+}
+
+/// A connection checked out of a `Pool`, returned to the pool when dropped
+pub struct PooledClient<'a> {
+    client: Option<Client>,
+    error: ConnectionError,
+    pool: &'a Pool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Pool {
+    /// Creates a pool that holds at most `max_size` open connections
+    pub fn new(config: DbConfig, db: Databases, max_size: usize) -> Pool {
+        Pool {
+            config,
+            db,
+            idle: Mutex::new(vec![]),
+            permits: Semaphore::new(max_size.max(1)),
+        }
+    }
+
+    /// Waits for a free connection, opening a new one if none are idle
+    pub async fn get(&self) -> Result<PooledClient<'_>> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|_| anyhow!("connection pool is closed"))?;
+
+        let idle = self.take_idle();
+        let (client, error) = match idle {
+            Some(idle) => idle,
+            None => open_db(&self.config, self.db).await?,
+        };
+
+        Ok(PooledClient {
+            client: Some(client),
+            error,
+            pool: self,
+            _permit: permit,
+        })
+    }
+
+    fn take_idle(&self) -> Option<(Client, ConnectionError)> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some((client, error)) = idle.pop() {
+            if !client.is_closed() {
+                return Some((client, error));
+            }
+        }
+        None
+    }
+}
+
+impl PooledClient<'_> {
+    /// Attaches the error that closed this client's connection, if it failed, to
+    /// the error of a query made with the client. Without it a query only reports
+    /// that the connection was closed.
+    pub fn context(&self, error: anyhow::Error) -> anyhow::Error {
+        match self.error.message() {
+            Some(message) => error.context(format!("connection failed: {}", message)),
+            None => error,
+        }
+    }
+}
+
+impl Deref for PooledClient<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledClient<'_> {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            if !client.is_closed() {
+                let error = self.error.clone();
+                self.pool.idle.lock().unwrap().push((client, error));
+            }
+        }
+    }
+}
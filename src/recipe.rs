@@ -1,10 +1,36 @@
-use crate::connect_db;
-use crate::Databases;
 use anyhow::{anyhow, Result};
 use futures::{pin_mut, TryStreamExt};
 use indexmap::IndexSet;
-use itertools::Itertools;
+use std::collections::BTreeMap;
 use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Row};
+
+// Every column of a recipe. The ingredients, tags, steps and comments are gathered
+// from the recipe_ingredient/ingredients, recipe_tag, steps and comments tables.
+// Comment bodies and votes come back as two arrays in the same order.
+const RECIPE_COLUMNS: &str = "recipes.id, recipes.title, recipes.publisher, recipes.url,
+    recipes.yields, recipes.time, recipes.description, recipes.num_ratings,
+    recipes.avg_rating,
+    ARRAY(SELECT ingredients.name
+          FROM recipe_ingredient
+          INNER JOIN ingredients
+          ON recipe_ingredient.ingredients_id = ingredients.id
+          WHERE recipe_ingredient.recipes_id = recipes.id) AS ingredients,
+    ARRAY(SELECT recipe_tag.tags_id
+          FROM recipe_tag
+          WHERE recipe_tag.recipes_id = recipes.id) AS tags,
+    ARRAY(SELECT steps.step
+          FROM steps
+          WHERE steps.recipes_id = recipes.id
+          ORDER BY steps.number) AS steps,
+    ARRAY(SELECT comments.body
+          FROM comments
+          WHERE comments.recipes_id = recipes.id
+          ORDER BY comments.id) AS comment_bodies,
+    ARRAY(SELECT comments.votes
+          FROM comments
+          WHERE comments.recipes_id = recipes.id
+          ORDER BY comments.id) AS comment_votes";
 
 #[derive(Debug)]
 pub struct Recipe {
@@ -24,8 +50,8 @@ pub struct Recipe {
     comments: Option<Vec<(String, usize)>>,
 }
 
-#[derive(Debug)]
-enum Publisher {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Publisher {
     /// New York Times Cooking
     NYTCooking = 0,
     /// allrecipes.com
@@ -33,7 +59,7 @@ enum Publisher {
 }
 
 impl Publisher {
-    fn new(value: i32) -> Result<Self> {
+    pub fn new(value: i32) -> Result<Self> {
         match value {
             0 => Ok(Self::NYTCooking),
             1 => Ok(Self::AllRecipes),
@@ -42,95 +68,380 @@ impl Publisher {
     }
 }
 
-pub async fn query_filtered_recipes(
-    tag: Option<String>,
-    all_ingredients: Option<Vec<String>>,
-    one_ingredient: Option<Vec<String>>,
-) -> Result<Vec<(i32, Vec<String>)>> {
-    let mut one_ingredient_query = None;
-    let mut all_ingredients_query = None;
-    let mut tag_query = None;
-    let mut variable_counter = 1;
-    let mut params = vec![];
-    let client = connect_db(Databases::Recipes).await?;
-
-    if let Some(mut list) = one_ingredient {
-        if list.len() != 0 {
-            let mut query = String::from(
-                "SELECT DISTINCT recipes.id
-                FROM recipes 
-                INNER JOIN recipe_ingredient
-                ON recipes.id = recipe_ingredient.recipes_id
-                INNER JOIN ingredients
-                ON recipe_ingredient.ingredients_id = ingredients.id
-                WHERE ",
-            );
-
-            query += &(0..list.len())
-                .map(|x| format!("ingredients.name = ${}", variable_counter + x))
-                .join(" OR ");
-
-            variable_counter += list.len();
-            one_ingredient_query = Some(query);
-            params.append(&mut list);
-        }
+impl Recipe {
+    fn from_row(row: &Row) -> Result<Self> {
+        let id: i32 = row.try_get("id")?;
+        let time: Option<i32> = row.try_get("time")?;
+        let num_ratings: Option<i32> = row.try_get("num_ratings")?;
+        let avg_rating: Option<f32> = row.try_get("avg_rating")?;
+        let tags: Vec<i32> = row.try_get("tags")?;
+        let comment_bodies: Vec<String> = row.try_get("comment_bodies")?;
+        let comment_votes: Vec<i32> = row.try_get("comment_votes")?;
+
+        Ok(Self {
+            id: Some(id as usize),
+            title: row.try_get("title")?,
+            source: Publisher::new(row.try_get("publisher")?)?,
+            url: row.try_get("url")?,
+            yields: row.try_get("yields")?,
+            time: time.map(|x| x as usize),
+            description: row.try_get("description")?,
+            tags: Some(tags.into_iter().map(|x| x as usize).collect()),
+            num_ratings: num_ratings.unwrap_or(0) as usize,
+            avg_rating: avg_rating.unwrap_or(0.0),
+            ingredients: row.try_get("ingredients")?,
+            steps: row.try_get("steps")?,
+            comments: Some(
+                comment_bodies
+                    .into_iter()
+                    .zip(comment_votes.into_iter().map(|x| x as usize))
+                    .collect(),
+            ),
+        })
     }
 
-    if let Some(mut list) = all_ingredients {
-        if list.len() != 0 {
-            all_ingredients_query = Some(
-                (0..list.len())
-                    .map(|x| {
-                        format!(
-                            "SELECT DISTINCT recipes.id
-                             FROM recipes 
-                             INNER JOIN recipe_ingredient
-                             ON recipes.id = recipe_ingredient.recipes_id
-                             INNER JOIN ingredients
-                             ON recipe_ingredient.ingredients_id = ingredients.id
-                             WHERE ingredients.name = ${}",
-                            variable_counter + x
-                        )
-                    })
-                    .join(" INTERSECT "),
-            );
-            variable_counter += list.len();
-            params.append(&mut list);
-        }
+    pub fn id(&self) -> Option<usize> {
+        self.id
     }
 
-    if let Some(tag) = tag {
-        tag_query = Some(format!(
-            "SELECT DISTINCT recipes.id
-             FROM recipes 
-             INNER JOIN recipe_tag
-             ON recipes.id = recipe_tag.recipes_id
-             INNER JOIN tags
-             ON recipe_tag.tags_id = tags.id
-             WHERE tags.name = ${}",
-            variable_counter
-        ));
-        params.push(tag);
+    pub fn title(&self) -> &str {
+        &self.title
     }
 
-    let sub_query = [one_ingredient_query, all_ingredients_query, tag_query]
-        .iter()
-        .filter_map(|x| x.as_ref())
-        .join(" INTERSECT ");
+    pub fn publisher(&self) -> Publisher {
+        self.source
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn yields(&self) -> &str {
+        &self.yields
+    }
+
+    /// Prep + Cooking time in minutes
+    pub fn time(&self) -> Option<usize> {
+        self.time
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// IDs of the tags on the recipe
+    pub fn tags(&self) -> Option<&IndexSet<usize>> {
+        self.tags.as_ref()
+    }
+
+    pub fn num_ratings(&self) -> usize {
+        self.num_ratings
+    }
+
+    pub fn avg_rating(&self) -> f32 {
+        self.avg_rating
+    }
+
+    pub fn ingredients(&self) -> &Vec<String> {
+        &self.ingredients
+    }
+
+    pub fn steps(&self) -> &Vec<String> {
+        &self.steps
+    }
+
+    /// Comment text along with its number of votes
+    pub fn comments(&self) -> Option<&Vec<(String, usize)>> {
+        self.comments.as_ref()
+    }
+}
+
+/// Fetches a single complete recipe by its ID
+pub async fn query_recipe(client: &Client, id: i32) -> Result<Recipe> {
+    query_recipes(client, &[id])
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("no recipe with id {}", id))
+}
 
+/// Fetches complete recipes for every ID found, in the same order as `ids`.
+/// IDs without a recipe are skipped.
+pub async fn query_recipes(client: &Client, ids: &[i32]) -> Result<Vec<Recipe>> {
     let query = format!(
-        "SELECT recipes.id, array_agg(DISTINCT ingredients.name) as ingredients_list
-                         FROM recipes
-                         INNER JOIN recipe_ingredient
-                         ON recipes.id = recipe_ingredient.recipes_id
-                         INNER JOIN ingredients
-                         ON recipe_ingredient.ingredients_id = ingredients.id
-                         WHERE recipes.id IN ({})
-                         GROUP BY recipes.id",
-        sub_query
+        "SELECT {} FROM recipes WHERE recipes.id = ANY($1)",
+        RECIPE_COLUMNS
     );
+    let rows = client.query(query.as_str(), &[&ids]).await?;
+
+    let mut recipes = rows
+        .iter()
+        .map(Recipe::from_row)
+        .collect::<Result<Vec<Recipe>>>()?;
+    let order: BTreeMap<usize, usize> = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id as usize, i))
+        .collect();
+    recipes.sort_by_key(|recipe| recipe.id.and_then(|id| order.get(&id).copied()));
+
+    Ok(recipes)
+}
+
+/// Whether a recipe needs to have any or all of the tags in a `RecipeFilter`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// Criteria for selecting recipes with `query_filtered_recipes`. Every criterion
+/// that is set must hold for a recipe to be included, an empty filter selects
+/// every recipe.
+#[derive(Debug, Clone, Default)]
+pub struct RecipeFilter {
+    tags: Vec<String>,
+    tag_match: TagMatch,
+    excluded_tags: Vec<String>,
+    any_ingredients: Vec<String>,
+    all_ingredients: Vec<String>,
+    excluded_ingredients: Vec<String>,
+    publisher: Option<Publisher>,
+    min_rating: Option<f32>,
+    min_num_ratings: Option<usize>,
+    max_time: Option<usize>,
+    limit: Option<usize>,
+}
+
+fn extend_unique<I, S>(list: &mut Vec<String>, values: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    for value in values {
+        let value = value.into();
+        if !list.contains(&value) {
+            list.push(value);
+        }
+    }
+}
+
+impl RecipeFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tag<S: Into<String>>(self, tag: S) -> Self {
+        self.tags(vec![tag])
+    }
+
+    pub fn tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extend_unique(&mut self.tags, tags);
+        self
+    }
+
+    /// Whether recipes need any (the default) or all of the tags
+    pub fn tag_match(mut self, tag_match: TagMatch) -> Self {
+        self.tag_match = tag_match;
+        self
+    }
+
+    /// Recipes must not have any of these tags
+    pub fn exclude_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extend_unique(&mut self.excluded_tags, tags);
+        self
+    }
+
+    /// Recipes must contain at least one of these ingredients
+    pub fn any_ingredients<I, S>(mut self, ingredients: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extend_unique(&mut self.any_ingredients, ingredients);
+        self
+    }
+
+    /// Recipes must contain every one of these ingredients
+    pub fn all_ingredients<I, S>(mut self, ingredients: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extend_unique(&mut self.all_ingredients, ingredients);
+        self
+    }
+
+    /// Recipes must not contain any of these ingredients, for example every dairy
+    /// product to select the dairy free recipes
+    pub fn exclude_ingredients<I, S>(mut self, ingredients: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        extend_unique(&mut self.excluded_ingredients, ingredients);
+        self
+    }
+
+    pub fn publisher(mut self, publisher: Publisher) -> Self {
+        self.publisher = Some(publisher);
+        self
+    }
+
+    /// Minimum average rating
+    pub fn min_rating(mut self, rating: f32) -> Self {
+        self.min_rating = Some(rating);
+        self
+    }
+
+    pub fn min_num_ratings(mut self, num_ratings: usize) -> Self {
+        self.min_num_ratings = Some(num_ratings);
+        self
+    }
+
+    /// Maximum prep + cooking time in minutes. Recipes without a time are excluded.
+    pub fn max_time(mut self, minutes: usize) -> Self {
+        self.max_time = Some(minutes);
+        self
+    }
+
+    /// Maximum number of recipes returned, the ones with the lowest IDs are kept
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Compiles the filter into a single query selecting `(recipe id, ingredient
+    /// names)` along with its parameters.
+    fn to_query(&self) -> (String, Vec<Box<dyn ToSql + Sync + Send>>) {
+        let mut conditions = vec![];
+        let mut params: Vec<Box<dyn ToSql + Sync + Send>> = vec![];
+
+        let ingredient_ids = "SELECT recipe_ingredient.recipes_id
+             FROM recipe_ingredient
+             INNER JOIN ingredients
+             ON recipe_ingredient.ingredients_id = ingredients.id
+             WHERE ingredients.name = ANY";
+        let tag_ids = "SELECT recipe_tag.recipes_id
+             FROM recipe_tag
+             INNER JOIN tags
+             ON recipe_tag.tags_id = tags.id
+             WHERE tags.name = ANY";
+
+        if !self.any_ingredients.is_empty() {
+            params.push(Box::new(self.any_ingredients.clone()));
+            conditions.push(format!(
+                "recipes.id IN ({}(${}))",
+                ingredient_ids,
+                params.len()
+            ));
+        }
+
+        if !self.all_ingredients.is_empty() {
+            params.push(Box::new(self.all_ingredients.clone()));
+            conditions.push(format!(
+                "recipes.id IN ({}(${n}::text[])
+                 GROUP BY recipe_ingredient.recipes_id
+                 HAVING COUNT(DISTINCT ingredients.name) = cardinality(${n}::text[]))",
+                ingredient_ids,
+                n = params.len()
+            ));
+        }
+
+        if !self.excluded_ingredients.is_empty() {
+            params.push(Box::new(self.excluded_ingredients.clone()));
+            conditions.push(format!(
+                "recipes.id NOT IN ({}(${}))",
+                ingredient_ids,
+                params.len()
+            ));
+        }
+
+        if !self.tags.is_empty() {
+            params.push(Box::new(self.tags.clone()));
+            conditions.push(match self.tag_match {
+                TagMatch::Any => format!("recipes.id IN ({}(${}))", tag_ids, params.len()),
+                TagMatch::All => format!(
+                    "recipes.id IN ({}(${n}::text[])
+                     GROUP BY recipe_tag.recipes_id
+                     HAVING COUNT(DISTINCT tags.name) = cardinality(${n}::text[]))",
+                    tag_ids,
+                    n = params.len()
+                ),
+            });
+        }
+
+        if !self.excluded_tags.is_empty() {
+            params.push(Box::new(self.excluded_tags.clone()));
+            conditions.push(format!(
+                "recipes.id NOT IN ({}(${}))",
+                tag_ids,
+                params.len()
+            ));
+        }
+
+        if let Some(publisher) = self.publisher {
+            params.push(Box::new(publisher as i32));
+            conditions.push(format!("recipes.publisher = ${}", params.len()));
+        }
+
+        if let Some(rating) = self.min_rating {
+            params.push(Box::new(rating));
+            conditions.push(format!("recipes.avg_rating >= ${}", params.len()));
+        }
+
+        if let Some(num_ratings) = self.min_num_ratings {
+            params.push(Box::new(num_ratings as i32));
+            conditions.push(format!("recipes.num_ratings >= ${}", params.len()));
+        }
+
+        if let Some(minutes) = self.max_time {
+            params.push(Box::new(minutes as i32));
+            conditions.push(format!("recipes.time <= ${}", params.len()));
+        }
+
+        let mut query = String::from(
+            "SELECT recipes.id, array_agg(DISTINCT ingredients.name) as ingredients_list
+             FROM recipes
+             INNER JOIN recipe_ingredient
+             ON recipes.id = recipe_ingredient.recipes_id
+             INNER JOIN ingredients
+             ON recipe_ingredient.ingredients_id = ingredients.id",
+        );
+
+        if !conditions.is_empty() {
+            query += "\nWHERE ";
+            query += &conditions.join("\nAND ");
+        }
+        query += "\nGROUP BY recipes.id\nORDER BY recipes.id";
+
+        if let Some(limit) = self.limit {
+            params.push(Box::new(limit as i64));
+            query += &format!("\nLIMIT ${}", params.len());
+        }
+
+        (query, params)
+    }
+}
+
+/// Queries the `(recipe id, ingredient names)` of every recipe matching the filter.
+/// The client can be a single connection from `connect_db` or one checked out of a
+/// `pool::Pool` when issuing many queries.
+pub async fn query_filtered_recipes(
+    client: &Client,
+    filter: &RecipeFilter,
+) -> Result<Vec<(i32, Vec<String>)>> {
+    let (query, params) = filter.to_query();
 
-    let params = params.iter().map(|p| p as &dyn ToSql);
+    let params = params.iter().map(|p| p.as_ref() as &dyn ToSql);
     let query = client.prepare(&query).await?;
     let row_stream = client.query_raw(&query, params).await?;
 
@@ -0,0 +1,273 @@
+use crate::co_occurrence::recipe_ingredient;
+use crate::graph::triplets;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// antecedent -> consequent, for example {flour, butter, sugar} -> {eggs}
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub antecedent: Vec<String>,
+    pub consequent: Vec<String>,
+    /// Fraction of recipes containing every ingredient of the rule
+    pub support: f64,
+    /// Fraction of the recipes with the antecedent that also have the consequent
+    pub confidence: f64,
+    /// Confidence divided by the support of the consequent, above 1 when the
+    /// antecedent makes the consequent more likely
+    pub lift: f64,
+}
+
+/// Mines frequent ingredient sets with Apriori and turns them into association
+/// rules
+pub struct RuleMiner {
+    /// Fraction of recipes an ingredient set must appear in to be frequent
+    pub min_support: f64,
+    pub min_confidence: f64,
+    /// Largest ingredient set considered
+    pub max_len: usize,
+}
+
+impl Default for RuleMiner {
+    fn default() -> Self {
+        Self {
+            min_support: 0.01,
+            min_confidence: 0.5,
+            max_len: 4,
+        }
+    }
+}
+
+impl RuleMiner {
+    /// Every ingredient set with at least the minimum support along with its
+    /// support, smallest sets first
+    pub async fn frequent_itemsets(
+        &self,
+        recipes: &Vec<(i32, Vec<String>)>,
+    ) -> Result<Vec<(Vec<String>, f64)>> {
+        let (itemsets, ingredients_list, num_recipes) = self.apriori(recipes).await?;
+
+        let mut itemsets: Vec<(Vec<String>, f64)> = itemsets
+            .into_iter()
+            .map(|(items, count)| {
+                let names = items.iter().map(|i| ingredients_list[*i].clone()).collect();
+                (names, count as f64 / num_recipes as f64)
+            })
+            .collect();
+        itemsets.sort_by_key(|(items, _)| items.len());
+
+        Ok(itemsets)
+    }
+
+    /// Every rule with at least the minimum support and confidence, highest lift
+    /// first
+    pub async fn mine(&self, recipes: &Vec<(i32, Vec<String>)>) -> Result<Vec<Rule>> {
+        let (itemsets, ingredients_list, num_recipes) = self.apriori(recipes).await?;
+        let num_recipes = num_recipes as f64;
+        let names = |items: &[usize]| -> Vec<String> {
+            items.iter().map(|i| ingredients_list[*i].clone()).collect()
+        };
+
+        let mut rules = vec![];
+        for (items, count) in itemsets.iter().filter(|(items, _)| items.len() > 1) {
+            // every non-empty proper subset is an antecedent
+            for mask in 1..(1usize << items.len()) - 1 {
+                let (antecedent, consequent): (Vec<_>, Vec<_>) = items
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .partition(|(position, _)| mask & (1 << position) != 0);
+                let antecedent: Vec<usize> = antecedent.into_iter().map(|(_, i)| i).collect();
+                let consequent: Vec<usize> = consequent.into_iter().map(|(_, i)| i).collect();
+
+                // subsets of frequent sets are always frequent
+                let confidence = *count as f64 / itemsets[&antecedent] as f64;
+                if confidence < self.min_confidence {
+                    continue;
+                }
+                let consequent_support = itemsets[&consequent] as f64 / num_recipes;
+
+                rules.push(Rule {
+                    antecedent: names(&antecedent),
+                    consequent: names(&consequent),
+                    support: *count as f64 / num_recipes,
+                    confidence,
+                    lift: confidence / consequent_support,
+                });
+            }
+        }
+
+        rules.sort_by(|a, b| {
+            b.lift
+                .partial_cmp(&a.lift)
+                .unwrap()
+                .then_with(|| b.confidence.partial_cmp(&a.confidence).unwrap())
+        });
+
+        Ok(rules)
+    }
+
+    // Frequent itemsets of ingredient IDs (sorted) with their recipe counts, the
+    // ingredient names and the number of recipes
+    async fn apriori(
+        &self,
+        recipes: &Vec<(i32, Vec<String>)>,
+    ) -> Result<(BTreeMap<Vec<usize>, usize>, Vec<String>, usize)> {
+        let (matrix, _, ingredients_list) = recipe_ingredient(recipes).await?;
+        let num_recipes = matrix.num_rows();
+        let min_count = ((self.min_support * num_recipes as f64).ceil() as usize).max(1);
+
+        let mut transactions = vec![vec![]; num_recipes];
+        let mut counts = vec![0; ingredients_list.len()];
+        for (recipe, ingredient, _) in triplets(&matrix) {
+            transactions[recipe].push(ingredient);
+            counts[ingredient] += 1;
+        }
+
+        // only frequent ingredients can be part of a frequent set
+        for transaction in transactions.iter_mut() {
+            transaction.retain(|i| counts[*i] >= min_count);
+            transaction.sort_unstable();
+        }
+
+        let mut frequent: BTreeMap<Vec<usize>, usize> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count >= min_count)
+            .map(|(i, count)| (vec![i], *count))
+            .collect();
+        let mut previous: Vec<Vec<usize>> = frequent.keys().cloned().collect();
+
+        for _ in 2..=self.max_len {
+            let candidates = join(&previous, &frequent);
+            if candidates.is_empty() {
+                break;
+            }
+
+            let mut candidate_counts = vec![0; candidates.len()];
+            for transaction in transactions.iter() {
+                for (candidate, count) in candidates.iter().zip(candidate_counts.iter_mut()) {
+                    if candidate.len() <= transaction.len()
+                        && candidate
+                            .iter()
+                            .all(|i| transaction.binary_search(i).is_ok())
+                    {
+                        *count += 1;
+                    }
+                }
+            }
+
+            previous = vec![];
+            for (candidate, count) in candidates.into_iter().zip(candidate_counts) {
+                if count >= min_count {
+                    previous.push(candidate.clone());
+                    frequent.insert(candidate, count);
+                }
+            }
+            if previous.is_empty() {
+                break;
+            }
+        }
+
+        Ok((frequent, ingredients_list, num_recipes))
+    }
+}
+
+// Apriori candidate generation. Sorted sets of size k - 1 that share their first
+// k - 2 items are joined, then candidates with an infrequent subset are dropped.
+fn join(previous: &[Vec<usize>], frequent: &BTreeMap<Vec<usize>, usize>) -> Vec<Vec<usize>> {
+    let mut candidates = vec![];
+
+    for (a, first) in previous.iter().enumerate() {
+        for second in previous[a + 1..].iter() {
+            let k = first.len();
+            if first[..k - 1] != second[..k - 1] {
+                continue;
+            }
+            let mut candidate = first.clone();
+            candidate.push(second[k - 1]);
+            candidate.sort_unstable();
+
+            let all_frequent = (0..candidate.len()).all(|skip| {
+                let subset: Vec<usize> = candidate
+                    .iter()
+                    .enumerate()
+                    .filter(|(position, _)| *position != skip)
+                    .map(|(_, i)| *i)
+                    .collect();
+                frequent.contains_key(&subset)
+            });
+            if all_frequent {
+                candidates.push(candidate);
+            }
+        }
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipes() -> Vec<(i32, Vec<String>)> {
+        vec![
+            vec!["flour", "butter", "sugar", "eggs"],
+            vec!["flour", "butter", "sugar"],
+            vec!["flour", "butter", "milk"],
+            vec!["flour", "sugar", "eggs"],
+            vec!["butter", "sugar", "salt"],
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, items)| (id as i32, items.into_iter().map(String::from).collect()))
+        .collect()
+    }
+
+    fn miner() -> RuleMiner {
+        RuleMiner {
+            min_support: 0.4,
+            min_confidence: 0.5,
+            max_len: 4,
+        }
+    }
+
+    #[tokio::test]
+    async fn frequent_itemsets_have_min_support() {
+        let itemsets = miner().frequent_itemsets(&recipes()).await.unwrap();
+
+        // {flour, butter, eggs} is pruned since {butter, eggs} is in one recipe
+        let expected = vec![
+            (vec!["flour"], 0.8),
+            (vec!["butter"], 0.8),
+            (vec!["sugar"], 0.8),
+            (vec!["eggs"], 0.4),
+            (vec!["flour", "butter"], 0.6),
+            (vec!["flour", "sugar"], 0.6),
+            (vec!["flour", "eggs"], 0.4),
+            (vec!["butter", "sugar"], 0.6),
+            (vec!["sugar", "eggs"], 0.4),
+            (vec!["flour", "butter", "sugar"], 0.4),
+            (vec!["flour", "sugar", "eggs"], 0.4),
+        ];
+        assert_eq!(itemsets.len(), expected.len());
+        for ((items, support), (expected_items, expected_support)) in itemsets.iter().zip(expected)
+        {
+            assert_eq!(items, &expected_items);
+            assert!((support - expected_support).abs() < 1e-9);
+        }
+    }
+
+    #[tokio::test]
+    async fn rules_have_support_confidence_and_lift() {
+        let rules = miner().mine(&recipes()).await.unwrap();
+        let rule = rules
+            .iter()
+            .find(|rule| rule.antecedent == ["flour", "butter"] && rule.consequent == ["sugar"])
+            .unwrap();
+
+        assert!((rule.support - 0.4).abs() < 1e-9);
+        assert!((rule.confidence - 2.0 / 3.0).abs() < 1e-9);
+        assert!((rule.lift - 2.0 / 3.0 / 0.8).abs() < 1e-9);
+        assert!(rules.iter().all(|rule| rule.confidence >= 0.5));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::co_occurrence::recipe_ingredient;
+use crate::graph::{build_matrix, triplets};
+use anyhow::{anyhow, Result};
+use matrixlab::matrix::sparse::SparseMatrix;
+use matrixlab::MatrixElement;
+use std::collections::BTreeMap;
+
+/// How the shared ingredients of two recipes are turned into a similarity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+    /// shared / (|a| + |b| - shared)
+    Jaccard,
+    /// shared / sqrt(|a| * |b|)
+    Cosine,
+    /// Cosine similarity of the ingredient vectors weighted by the inverse document
+    /// frequency ln(N / c_i), so rare shared ingredients count for more
+    TfIdf,
+}
+
+/// Recipe x recipe similarity, the dual of the ingredient x ingredient
+/// co-occurrence. Where `co_occurrence::make_relation` builds R^T * R this builds a
+/// weighted R * R^T.
+pub struct RecipeSimilarity {
+    // Indexed on row
+    recipe_ids: Vec<i32>,
+    // Key: recipe ID Value: row
+    rows: BTreeMap<i32, usize>,
+    // Ingredient IDs of each recipe
+    recipes: Vec<Vec<usize>>,
+    // Rows of the recipes containing each ingredient
+    postings: Vec<Vec<usize>>,
+    // Weight of each ingredient, indexed on ingredient ID
+    weights: Vec<f64>,
+    // Length of each recipe's weighted ingredient vector
+    norms: Vec<f64>,
+    weighting: Weighting,
+}
+
+impl RecipeSimilarity {
+    pub async fn new(
+        recipes: &Vec<(i32, Vec<String>)>,
+        weighting: Weighting,
+    ) -> Result<RecipeSimilarity> {
+        let (matrix, _, ingredients_list) = recipe_ingredient(recipes).await?;
+        let num_recipes = matrix.num_rows();
+
+        let mut ingredients = vec![vec![]; num_recipes];
+        let mut postings = vec![vec![]; ingredients_list.len()];
+        for (recipe, ingredient, _) in triplets(&matrix) {
+            ingredients[recipe].push(ingredient);
+            postings[ingredient].push(recipe);
+        }
+
+        let weights: Vec<f64> = postings
+            .iter()
+            .map(|recipes| match weighting {
+                Weighting::TfIdf => (num_recipes as f64 / recipes.len() as f64).ln(),
+                _ => 1.0,
+            })
+            .collect();
+        let norms = ingredients
+            .iter()
+            .map(|recipe| {
+                recipe
+                    .iter()
+                    .map(|i| weights[*i] * weights[*i])
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .collect();
+
+        let recipe_ids: Vec<i32> = recipes.iter().map(|(id, _)| *id).collect();
+        let rows = recipe_ids
+            .iter()
+            .enumerate()
+            .map(|(row, id)| (*id, row))
+            .collect();
+
+        Ok(RecipeSimilarity {
+            recipe_ids,
+            rows,
+            recipes: ingredients,
+            postings,
+            weights,
+            norms,
+            weighting,
+        })
+    }
+
+    /// Similarity of every pair of recipes sharing at least one ingredient, indexed
+    /// in the same order as the recipes that were passed in. The diagonal is left
+    /// out.
+    pub fn similarity_matrix(&self) -> SparseMatrix<f64> {
+        let n = self.recipe_ids.len();
+        let matrix_elements = (0..n)
+            .flat_map(|row| {
+                self.scores(row)
+                    .into_iter()
+                    .map(move |(other, score)| MatrixElement::new(row, other, score))
+            })
+            .collect();
+
+        build_matrix(n, n, matrix_elements)
+    }
+
+    /// The k recipes most similar to the recipe with the given ID along with their
+    /// similarity, most similar first
+    pub fn nearest_recipes(&self, recipe_id: i32, k: usize) -> Result<Vec<(i32, f64)>> {
+        let row = *self
+            .rows
+            .get(&recipe_id)
+            .ok_or_else(|| anyhow!("recipe {} is not in the set", recipe_id))?;
+
+        let mut scores = self.scores(row);
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+
+        Ok(scores
+            .into_iter()
+            .take(k)
+            .map(|(other, score)| (self.recipe_ids[other], score))
+            .collect())
+    }
+
+    // Similarity from one recipe to every other recipe it shares an ingredient with
+    fn scores(&self, row: usize) -> Vec<(usize, f64)> {
+        let mut shared: BTreeMap<usize, f64> = BTreeMap::new();
+        for &ingredient in self.recipes[row].iter() {
+            let weight = self.weights[ingredient] * self.weights[ingredient];
+            for &other in self.postings[ingredient].iter() {
+                if other != row {
+                    *shared.entry(other).or_insert(0.0) += weight;
+                }
+            }
+        }
+
+        shared
+            .into_iter()
+            .map(|(other, dot)| {
+                let score = match self.weighting {
+                    Weighting::Jaccard => {
+                        let union = self.recipes[row].len() + self.recipes[other].len();
+                        dot / (union as f64 - dot)
+                    }
+                    Weighting::Cosine | Weighting::TfIdf => {
+                        let norms = self.norms[row] * self.norms[other];
+                        if norms > 0.0 {
+                            dot / norms
+                        } else {
+                            0.0
+                        }
+                    }
+                };
+                (other, score)
+            })
+            .collect()
+    }
+}
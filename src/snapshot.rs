@@ -0,0 +1,375 @@
+use crate::co_occurrence::Relation;
+use crate::expanded::ExpandedIngredientRelation;
+use crate::hierarchy::Hierarchy;
+use anyhow::{anyhow, Result};
+use serde::de::{DeserializeOwned, Error, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+/// Incremented whenever the serialized form of one of the snapshot types changes.
+/// Snapshots written with a different version are rejected instead of being
+/// misread.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Header<'a> {
+    version: u32,
+    kind: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OwnedHeader {
+    version: u32,
+    kind: String,
+}
+
+// Reads a JSON snapshot in a single pass. The header is checked before the data
+// is deserialized when it comes first, as it does in snapshots from `save_json`.
+struct JsonEnvelope<T> {
+    kind: &'static str,
+    data: PhantomData<T>,
+}
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for JsonEnvelope<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a {} snapshot", self.kind)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut version = None;
+        let mut kind: Option<String> = None;
+        let mut data = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value()?),
+                "kind" => kind = Some(map.next_value()?),
+                "data" => {
+                    if let (Some(version), Some(kind)) = (version, &kind) {
+                        check_header(version, kind, self.kind).map_err(A::Error::custom)?;
+                    }
+                    data = Some(map.next_value()?);
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let version = version.ok_or_else(|| A::Error::missing_field("version"))?;
+        let kind = kind.ok_or_else(|| A::Error::missing_field("kind"))?;
+        check_header(version, &kind, self.kind).map_err(A::Error::custom)?;
+        data.ok_or_else(|| A::Error::missing_field("data"))
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    version: u32,
+    kind: &'a str,
+    data: &'a T,
+}
+
+/// Persisting a full analysis to JSON or a compact binary form so it can be
+/// reloaded without the database or rerunning the partitioning. Every snapshot
+/// records `SNAPSHOT_VERSION` and the kind of value it holds.
+pub trait Snapshot: Serialize + DeserializeOwned {
+    /// Name stored in the snapshot so loading one type from another's snapshot
+    /// fails cleanly
+    const KIND: &'static str;
+
+    fn save_json<W: Write>(&self, writer: W) -> Result<()> {
+        let envelope = Envelope {
+            version: SNAPSHOT_VERSION,
+            kind: Self::KIND,
+            data: self,
+        };
+        serde_json::to_writer(writer, &envelope)?;
+        Ok(())
+    }
+
+    fn load_json<R: Read>(reader: R) -> Result<Self> {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let value = deserializer.deserialize_map(JsonEnvelope::<Self> {
+            kind: Self::KIND,
+            data: PhantomData,
+        })?;
+        deserializer.end()?;
+
+        value.validate()?;
+        Ok(value)
+    }
+
+    fn save_binary<W: Write>(&self, mut writer: W) -> Result<()> {
+        let header = Header {
+            version: SNAPSHOT_VERSION,
+            kind: Self::KIND,
+        };
+        bincode::serialize_into(&mut writer, &header)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn load_binary<R: Read>(mut reader: R) -> Result<Self> {
+        let header: OwnedHeader = bincode::deserialize_from(&mut reader)?;
+        check_header(header.version, &header.kind, Self::KIND)?;
+
+        let value: Self = bincode::deserialize_from(reader)?;
+        value.validate()?;
+        Ok(value)
+    }
+
+    /// Checks that a loaded value is consistent so a tampered or mismatched
+    /// snapshot is rejected instead of panicking later
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Snapshot for Relation {
+    const KIND: &'static str = "relation";
+
+    fn validate(&self) -> Result<()> {
+        let ingredients_list = self.get_ingredient_list();
+        let matrix = self.get_matrix();
+        let n = ingredients_list.len();
+
+        if self.get_ingredient_map().len() != n {
+            return Err(anyhow!(
+                "{} ingredients in the map but {} in the list",
+                self.get_ingredient_map().len(),
+                n
+            ));
+        }
+        if matrix.num_rows() != n || matrix.num_columns() != n {
+            return Err(anyhow!(
+                "{} x {} co-occurrence matrix for {} ingredients",
+                matrix.num_rows(),
+                matrix.num_columns(),
+                n
+            ));
+        }
+        for (name, (id, _)) in self.get_ingredient_map() {
+            if ingredients_list.get(*id) != Some(name) {
+                return Err(anyhow!("{} does not match its ID {}", name, id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for ExpandedIngredientRelation {
+    const KIND: &'static str = "expanded_relation";
+
+    fn validate(&self) -> Result<()> {
+        self.check_edges()
+    }
+}
+
+impl Snapshot for Hierarchy {
+    const KIND: &'static str = "hierarchy";
+}
+
+fn check_header(version: u32, found: &str, kind: &str) -> Result<()> {
+    if found != kind {
+        return Err(anyhow!("snapshot holds a {}, expected a {}", found, kind));
+    }
+    if version != SNAPSHOT_VERSION {
+        return Err(anyhow!(
+            "snapshot version {} is not supported, expected {}",
+            version,
+            SNAPSHOT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// serde `with` module for a `SparseMatrix<usize>` stored as its CSR arrays
+pub(crate) mod csr {
+    use matrixlab::matrix::sparse::SparseMatrix;
+    use matrixlab::MatrixElement;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct Csr<'a> {
+        rows: usize,
+        columns: usize,
+        indptr: &'a [usize],
+        indices: &'a [usize],
+        data: &'a [usize],
+    }
+
+    #[derive(Deserialize)]
+    pub(super) struct OwnedCsr {
+        rows: usize,
+        columns: usize,
+        indptr: Vec<usize>,
+        indices: Vec<usize>,
+        data: Vec<usize>,
+    }
+
+    pub fn serialize<S: Serializer>(
+        matrix: &SparseMatrix<usize>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Csr {
+            rows: matrix.num_rows(),
+            columns: matrix.num_columns(),
+            indptr: matrix.get_rows(),
+            indices: matrix.get_columns(),
+            data: matrix.get_data(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SparseMatrix<usize>, D::Error> {
+        OwnedCsr::deserialize(deserializer)?
+            .into_matrix()
+            .map_err(D::Error::custom)
+    }
+
+    impl OwnedCsr {
+        pub(super) fn into_matrix(self) -> Result<SparseMatrix<usize>, String> {
+            let nnz = self.data.len();
+            if self.indptr.len() != self.rows + 1
+                || self.indptr[0] != 0
+                || self.indptr[self.rows] != nnz
+                || self.indices.len() != nnz
+                || self.indptr.windows(2).any(|x| x[0] > x[1])
+            {
+                return Err("malformed CSR arrays".into());
+            }
+
+            let mut matrix_elements = Vec::with_capacity(nnz);
+            for row in 0..self.rows {
+                for k in self.indptr[row]..self.indptr[row + 1] {
+                    if self.indices[k] >= self.columns {
+                        return Err(format!(
+                            "column index {} is out of bounds for {} columns",
+                            self.indices[k], self.columns
+                        ));
+                    }
+                    matrix_elements.push(MatrixElement::new(row, self.indices[k], self.data[k]));
+                }
+            }
+
+            SparseMatrix::new(self.rows, self.columns, matrix_elements)
+                .map_err(|e| format!("invalid matrix: {:?}", e))
+        }
+    }
+}
+
+/// serde `with` module for a Vec of `SparseMatrix<usize>`, see `csr`
+pub(crate) mod csr_vec {
+    use super::csr;
+    use matrixlab::matrix::sparse::SparseMatrix;
+    use serde::de::Error;
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    struct Matrix<'a>(&'a SparseMatrix<usize>);
+
+    impl Serialize for Matrix<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            csr::serialize(self.0, serializer)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        matrices: &[SparseMatrix<usize>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(matrices.len()))?;
+        for matrix in matrices {
+            seq.serialize_element(&Matrix(matrix))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<SparseMatrix<usize>>, D::Error> {
+        Vec::<csr::OwnedCsr>::deserialize(deserializer)?
+            .into_iter()
+            .map(|matrix| matrix.into_matrix().map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// serde `with` module for an edge map keyed on (i, j), stored as a Vec of
+/// (i, j, weight) since JSON objects can only have string keys
+pub(crate) mod edge_list {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(
+        edges: &BTreeMap<(usize, usize), usize>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(edges.iter().map(|((i, j), weight)| (i, j, weight)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<(usize, usize), usize>, D::Error> {
+        let edges = Vec::<(usize, usize, usize)>::deserialize(deserializer)?;
+        Ok(edges
+            .into_iter()
+            .map(|(i, j, weight)| ((i, j), weight))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::co_occurrence::tests::relation;
+
+    #[tokio::test]
+    async fn relation_round_trips() {
+        let relation = relation().await;
+
+        let mut json = vec![];
+        relation.save_json(&mut json).unwrap();
+        let loaded = Relation::load_json(&json[..]).unwrap();
+        assert_eq!(loaded.get_counts(), relation.get_counts());
+
+        let mut binary = vec![];
+        relation.save_binary(&mut binary).unwrap();
+        let loaded = Relation::load_binary(&binary[..]).unwrap();
+        assert_eq!(loaded.get_ingredient_map(), relation.get_ingredient_map());
+        assert_eq!(loaded.get_recipe_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn mismatched_relation_is_rejected() {
+        let mut json = vec![];
+        relation().await.save_json(&mut json).unwrap();
+        let mut value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        value["data"]["ingredients_list"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+
+        let json = serde_json::to_vec(&value).unwrap();
+        assert!(Relation::load_json(&json[..]).is_err());
+        assert!(ExpandedIngredientRelation::load_json(&json[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn header_is_checked_before_the_data() {
+        let mut json = vec![];
+        relation().await.save_json(&mut json).unwrap();
+
+        let error = Hierarchy::load_json(&json[..]).err().unwrap();
+        assert!(error.to_string().contains("holds a relation"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::pool::Pool;
+use crate::recipe::{query_filtered_recipes, RecipeFilter};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Future returned by `RecipeSource::load_recipes`
+pub type RecipesFuture<'a> = BoxFuture<'a, Result<Vec<(i32, Vec<String>)>>>;
+
+/// Anything that can provide the `(recipe id, ingredient names)` pairs that
+/// `co_occurrence::make_relation` and friends are built from.
+pub trait RecipeSource {
+    fn load_recipes(&self) -> RecipesFuture<'_>;
+}
+
+/// Recipes queried from the recipes database with `recipe::query_filtered_recipes`
+pub struct DatabaseSource {
+    pool: Arc<Pool>,
+    filter: RecipeFilter,
+}
+
+impl DatabaseSource {
+    pub fn new(pool: Arc<Pool>, filter: RecipeFilter) -> Self {
+        Self { pool, filter }
+    }
+}
+
+impl RecipeSource for DatabaseSource {
+    fn load_recipes(&self) -> RecipesFuture<'_> {
+        Box::pin(async move {
+            let client = self.pool.get().await?;
+            query_filtered_recipes(&client, &self.filter)
+                .await
+                .map_err(|e| client.context(e))
+        })
+    }
+}
+
+/// One recipe as stored in a recipe file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeRecord {
+    pub id: i32,
+    pub ingredients: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// One JSON object per line, `{"id": 1, "ingredients": [...], "tags": [...]}`
+    JsonLines,
+    /// `id,ingredients,tags` rows where the ingredients and tags fields are `;`
+    /// separated lists. Fields may be double quoted and a header row is optional.
+    Csv,
+}
+
+/// Recipes read from a local file so analyses can run without the database
+pub struct FileSource {
+    path: PathBuf,
+    format: FileFormat,
+}
+
+impl FileSource {
+    /// Guesses the format from the file extension, `.csv` is read as CSV and
+    /// anything else as JSON lines.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => FileFormat::Csv,
+            _ => FileFormat::JsonLines,
+        };
+        Self { path, format }
+    }
+
+    pub fn with_format<P: AsRef<Path>>(path: P, format: FileFormat) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            format,
+        }
+    }
+
+    /// Reads every recipe in the file, including the tags
+    pub async fn read_records(&self) -> Result<Vec<RecipeRecord>> {
+        let data = tokio::fs::read_to_string(&self.path).await?;
+
+        match self.format {
+            FileFormat::JsonLines => parse_json_lines(&data),
+            FileFormat::Csv => parse_csv(&data),
+        }
+    }
+}
+
+impl RecipeSource for FileSource {
+    fn load_recipes(&self) -> RecipesFuture<'_> {
+        Box::pin(async move {
+            let records = self.read_records().await?;
+            Ok(records
+                .into_iter()
+                .map(|record| (record.id, record.ingredients))
+                .collect())
+        })
+    }
+}
+
+fn parse_json_lines(data: &str) -> Result<Vec<RecipeRecord>> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+fn parse_csv(data: &str) -> Result<Vec<RecipeRecord>> {
+    let mut records = vec![];
+
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(line).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+
+        let id = match fields[0].trim().parse() {
+            Ok(id) => id,
+            // header row
+            Err(_) if i == 0 => continue,
+            Err(_) => return Err(anyhow!("line {}: invalid recipe id", i + 1)),
+        };
+        if fields.len() < 2 {
+            return Err(anyhow!("line {}: missing ingredients", i + 1));
+        }
+
+        records.push(RecipeRecord {
+            id,
+            ingredients: split_list(&fields[1]),
+            tags: fields.get(2).map(|x| split_list(x)).unwrap_or_default(),
+        });
+    }
+
+    Ok(records)
+}
+
+fn split_list(field: &str) -> Vec<String> {
+    field
+        .split(';')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Splits a line of CSV into its fields. Fields can be wrapped in double quotes to
+/// contain commas, and a doubled quote inside of a quoted field is a literal quote.
+pub(crate) fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(anyhow!("unterminated quoted field"));
+    }
+    fields.push(field);
+
+    Ok(fields)
+}
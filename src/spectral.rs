@@ -0,0 +1,138 @@
+use crate::graph::{interpolation_matrix, Graph};
+use matrixlab::matrix::sparse::SparseMatrix;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Recursive spectral bisection of a symmetric adjacency matrix. Every cluster is
+/// split in two by the sign of the Fiedler vector of its normalized Laplacian until
+/// the tree is `levels` deep. Returns a Vec of interpolation matrices in the same
+/// form as `louvain::louvain`, the first one mapping vertices onto the (up to)
+/// 2^levels leaves of the tree and the last one mapping onto the two halves of the
+/// first split. `iterations` is the number of power iterations used to find each
+/// Fiedler vector.
+pub fn spectral(
+    adjacency: &SparseMatrix<usize>,
+    levels: usize,
+    iterations: usize,
+    seed: u64,
+) -> Vec<SparseMatrix<usize>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let graph = Graph::from_matrix(adjacency);
+
+    // clusters at each depth of the tree along with the index of their parent
+    let mut clusters: Vec<Vec<usize>> = vec![(0..graph.num_nodes()).collect()];
+    let mut parents: Vec<Vec<usize>> = vec![];
+
+    for _ in 0..levels.max(1) {
+        let mut children = vec![];
+        let mut child_parents = vec![];
+
+        for (parent, cluster) in clusters.iter().enumerate() {
+            let (left, right) = bisect(&graph, cluster, iterations, &mut rng);
+            for half in vec![left, right].into_iter().filter(|x| !x.is_empty()) {
+                children.push(half);
+                child_parents.push(parent);
+            }
+        }
+
+        parents.push(child_parents);
+        clusters = children;
+    }
+
+    let mut membership = vec![0; graph.num_nodes()];
+    for (leaf, cluster) in clusters.iter().enumerate() {
+        for &node in cluster {
+            membership[node] = leaf;
+        }
+    }
+
+    let mut interpolation_matrices = vec![interpolation_matrix(&membership, clusters.len())];
+    for depth in (1..parents.len()).rev() {
+        interpolation_matrices.push(interpolation_matrix(
+            &parents[depth],
+            parents[depth - 1].len(),
+        ));
+    }
+
+    interpolation_matrices
+}
+
+// Splits a cluster by the sign of its Fiedler vector, falling back to the median
+// when every entry has the same sign.
+fn bisect(
+    graph: &Graph,
+    cluster: &[usize],
+    iterations: usize,
+    rng: &mut StdRng,
+) -> (Vec<usize>, Vec<usize>) {
+    if cluster.len() < 2 {
+        return (cluster.to_vec(), vec![]);
+    }
+
+    let fiedler = fiedler_vector(&graph.subgraph(cluster), iterations, rng);
+    let mut split = 0.0;
+    if fiedler.iter().all(|x| *x >= 0.0) || fiedler.iter().all(|x| *x < 0.0) {
+        let mut sorted = fiedler.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        split = sorted[sorted.len() / 2];
+    }
+
+    let (left, right): (Vec<_>, Vec<_>) = cluster
+        .iter()
+        .copied()
+        .zip(fiedler)
+        .partition(|(_, x)| *x < split);
+
+    (
+        left.into_iter().map(|(node, _)| node).collect(),
+        right.into_iter().map(|(node, _)| node).collect(),
+    )
+}
+
+// Power iteration on (I + D^-1/2 A D^-1/2) / 2 with the trivial eigenvector
+// D^1/2 1 projected out. The second largest eigenvector of that matrix is the
+// second smallest eigenvector of the normalized Laplacian, which is rescaled by
+// D^-1/2 to give the Fiedler vector.
+fn fiedler_vector(graph: &Graph, iterations: usize, rng: &mut StdRng) -> Vec<f64> {
+    let n = graph.num_nodes();
+    let sqrt_degrees: Vec<f64> = (0..n).map(|node| graph.degree(node).sqrt()).collect();
+    let norm = sqrt_degrees.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let trivial: Vec<f64> = sqrt_degrees
+        .iter()
+        .map(|x| if norm > 0.0 { x / norm } else { 0.0 })
+        .collect();
+
+    let mut vector: Vec<f64> = (0..n).map(|_| rng.gen::<f64>() - 0.5).collect();
+
+    for _ in 0..iterations {
+        let projection: f64 = vector.iter().zip(trivial.iter()).map(|(x, y)| x * y).sum();
+        for (x, y) in vector.iter_mut().zip(trivial.iter()) {
+            *x -= projection * y;
+        }
+
+        let mut next: Vec<f64> = vector.iter().map(|x| x / 2.0).collect();
+        for node in 0..n {
+            if sqrt_degrees[node] == 0.0 {
+                continue;
+            }
+            for &(neighbor, weight) in graph.neighbors(node) {
+                if sqrt_degrees[neighbor] > 0.0 {
+                    next[node] += weight * vector[neighbor]
+                        / (2.0 * sqrt_degrees[node] * sqrt_degrees[neighbor]);
+                }
+            }
+        }
+
+        let length = next.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if length == 0.0 {
+            break;
+        }
+        vector = next.into_iter().map(|x| x / length).collect();
+    }
+
+    vector
+        .iter()
+        .zip(sqrt_degrees.iter())
+        .map(|(x, d)| if *d > 0.0 { x / d } else { *x })
+        .collect()
+}
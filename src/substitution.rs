@@ -0,0 +1,103 @@
+use crate::co_occurrence::{Association, Relation};
+use crate::graph::triplets;
+use anyhow::{anyhow, Result};
+
+/// Ranks substitutes for an ingredient. A good substitute appears with the same
+/// partners as the ingredient, so its context is similar, but rarely appears in the
+/// same recipe as it. Contexts are the positive PMI rows of the co-occurrence
+/// matrix and are compared with cosine similarity, leaving out the two ingredients
+/// being compared. The score is
+/// context similarity * (1 - penalty * c_ab / min(c_a, c_b))
+/// where c_ab / min(c_a, c_b) is the fraction of the rarer ingredient's recipes that
+/// also have the other one.
+pub struct SubstitutionScorer {
+    /// How strongly appearing together counts against a substitute, 0 ignores it
+    pub penalty: f64,
+    /// Candidates found in fewer recipes are not considered
+    pub min_count: usize,
+}
+
+impl Default for SubstitutionScorer {
+    fn default() -> Self {
+        Self {
+            penalty: 1.0,
+            min_count: 1,
+        }
+    }
+}
+
+impl SubstitutionScorer {
+    /// The k best substitutes for the ingredient and their scores, best first
+    pub fn substitutes(
+        &self,
+        relation: &Relation,
+        ingredient: &str,
+        k: usize,
+    ) -> Result<Vec<(String, f64)>> {
+        let target = relation
+            .get_ingredient_map()
+            .get(ingredient)
+            .ok_or_else(|| anyhow!("unknown ingredient: {}", ingredient))?
+            .0;
+
+        let counts = relation.get_counts();
+        let recipes = relation.get_recipe_count() as f64;
+        let n = counts.len();
+
+        // positive PMI context of every ingredient along with the raw co-occurrence
+        // counts with the target
+        let mut contexts: Vec<Vec<(usize, f64)>> = vec![vec![]; n];
+        let mut together = vec![0.0; n];
+        for (i, j, count) in triplets(relation.get_matrix()) {
+            if i == j {
+                continue;
+            }
+            if i == target {
+                together[j] = count as f64;
+            }
+            let pmi =
+                Association::Pmi.score(count as f64, counts[i] as f64, counts[j] as f64, recipes);
+            if pmi > 0.0 {
+                contexts[i].push((j, pmi));
+            }
+        }
+
+        let mut target_context = vec![0.0; n];
+        for &(j, value) in contexts[target].iter() {
+            target_context[j] = value;
+        }
+        let target_norm: f64 = target_context.iter().map(|x| x * x).sum();
+
+        let mut scores: Vec<(usize, f64)> = (0..n)
+            .filter(|&candidate| candidate != target && counts[candidate] >= self.min_count)
+            .filter_map(|candidate| {
+                let mut dot = 0.0;
+                let mut norm = 0.0;
+                for &(j, value) in contexts[candidate].iter() {
+                    if j != target {
+                        dot += value * target_context[j];
+                        norm += value * value;
+                    }
+                }
+                let norm = norm * (target_norm - target_context[candidate].powi(2));
+                if dot <= 0.0 || norm <= 0.0 {
+                    return None;
+                }
+
+                let rarer = counts[target].min(counts[candidate]) as f64;
+                let direct = together[candidate] / rarer;
+                let score = dot / norm.sqrt() * (1.0 - self.penalty * direct);
+                Some((candidate, score))
+            })
+            .collect();
+
+        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+
+        let names = relation.get_ingredient_list();
+        Ok(scores
+            .into_iter()
+            .take(k)
+            .map(|(candidate, score)| (names[candidate].clone(), score))
+            .collect())
+    }
+}
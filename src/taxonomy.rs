@@ -0,0 +1,150 @@
+use crate::co_occurrence::{recipe_ingredient, Relation};
+use crate::graph::{build_matrix, triplets};
+use crate::source::split_csv_line;
+use anyhow::{anyhow, Result};
+use matrixlab::MatrixElement;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Multi-level mapping of ingredients onto categories, for example
+/// "basil" -> "herb" -> "produce". Level 0 is the most specific category.
+pub struct Taxonomy {
+    // Key: ingredient name Value: categories from most specific to most general
+    paths: BTreeMap<String, Vec<String>>,
+}
+
+impl Taxonomy {
+    pub fn new() -> Self {
+        Self {
+            paths: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the categories of an ingredient, most specific first
+    pub fn insert(&mut self, ingredient: String, categories: Vec<String>) {
+        self.paths.insert(ingredient, categories);
+    }
+
+    /// Reads a taxonomy from a file. `.json` files hold an object mapping each
+    /// ingredient to its list of categories, anything else is read as CSV lines of
+    /// `ingredient,category,parent category,...`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)?;
+
+        let mut taxonomy = Self::new();
+
+        if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            let paths: BTreeMap<String, Vec<String>> = serde_json::from_str(&data)?;
+            for (ingredient, categories) in paths {
+                let categories: Vec<String> = categories
+                    .into_iter()
+                    .filter(|x| !x.trim().is_empty())
+                    .collect();
+                if categories.is_empty() {
+                    return Err(anyhow!("{} has no category", ingredient));
+                }
+                taxonomy.insert(ingredient, categories);
+            }
+            return Ok(taxonomy);
+        }
+
+        for (i, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = split_csv_line(line).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+            let mut fields = fields.into_iter().map(|x| x.trim().to_string());
+            let ingredient = fields.next().unwrap();
+            let categories: Vec<String> = fields.filter(|x| !x.is_empty()).collect();
+            if categories.is_empty() {
+                return Err(anyhow!("line {}: {} has no category", i + 1, ingredient));
+            }
+            taxonomy.insert(ingredient, categories);
+        }
+
+        Ok(taxonomy)
+    }
+
+    /// Category of the ingredient at a level. Levels past the end of an
+    /// ingredient's categories give its most general category.
+    pub fn category(&self, ingredient: &str, level: usize) -> Option<&str> {
+        self.paths.get(ingredient).and_then(|categories| {
+            categories
+                .get(level)
+                .or_else(|| categories.last())
+                .map(|x| x.as_str())
+        })
+    }
+
+    /// Number of levels of the deepest ingredient
+    pub fn depth(&self) -> usize {
+        self.paths.values().map(|x| x.len()).max().unwrap_or(0)
+    }
+}
+
+impl Default for Taxonomy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a category x category `Relation` at one level of the taxonomy. The
+/// columns of the recipe x ingredient matrix are merged through the taxonomy so a
+/// recipe contains a category when it has any ingredient in it, and the counts are
+/// the number of recipes containing each category. Ingredients missing from the
+/// taxonomy are left out.
+pub async fn make_category_relation(
+    recipes: &Vec<(i32, Vec<String>)>,
+    taxonomy: &Taxonomy,
+    level: usize,
+) -> Result<Relation> {
+    let (recipe_ingredient, _, ingredients_list) = recipe_ingredient(recipes).await?;
+
+    let mut category_ids: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut categories_list = vec![];
+    let ingredient_category: Vec<Option<usize>> = ingredients_list
+        .iter()
+        .map(|ingredient| {
+            taxonomy.category(ingredient, level).map(|category| {
+                *category_ids.entry(category).or_insert_with(|| {
+                    categories_list.push(category.to_string());
+                    categories_list.len() - 1
+                })
+            })
+        })
+        .collect();
+
+    let mut recipe_category = BTreeSet::new();
+    for (recipe, ingredient, _) in triplets(&recipe_ingredient) {
+        if let Some(category) = ingredient_category[ingredient] {
+            recipe_category.insert((recipe, category));
+        }
+    }
+
+    let mut counts = vec![0; categories_list.len()];
+    let matrix_elements = recipe_category
+        .into_iter()
+        .map(|(recipe, category)| {
+            counts[category] += 1;
+            MatrixElement::new(recipe, category, 1)
+        })
+        .collect();
+    let recipe_category = build_matrix(
+        recipe_ingredient.num_rows(),
+        categories_list.len(),
+        matrix_elements,
+    );
+
+    let category_map = categories_list
+        .iter()
+        .enumerate()
+        .map(|(id, name)| (name.clone(), (id, counts[id])))
+        .collect();
+
+    Ok(Relation::from_recipe_ingredient(
+        &recipe_category,
+        category_map,
+        categories_list,
+    ))
+}